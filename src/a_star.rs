@@ -1,154 +1,262 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
 use macroquad::prelude::*;
 
 use crate::primitives::*;
-use crate::level::*;
-
-struct Node<'a> {
-	pub pos: Vec2,
-	pub parent: Option<&'a Self>,
-	pub dist_from_start: f32,
-	pub dist_from_target: f32,
-	pub cost: f32,
+use crate::generation::*;
+
+// Costs are kept in tenths of a tile so diagonals can be close to √2 without floats in the heap
+pub const STRAIGHT_COST: i32 = 10;
+pub const DIAGONAL_COST: i32 = 14;
+// Gives up after looking at this many tiles, so an unreachable target can't stall a turn
+pub const MAX_SEARCH: usize = 4000;
+
+pub fn to_tile(pos: Vec2) -> IVec2 {
+	(pos / T_SIZE).floor().as_ivec2()
 }
 
-impl<'a> Node<'a> {
-	pub fn new(pos: Vec2, parent: Option<&'a Node>) -> Self {
-		Self {
-			pos,
-			parent,
-			dist_from_start: if let Some(p) = parent {
-				p.dist_from_start + 1.
-			} else {
-				0.
-			},
-			dist_from_target: 0.,
-			cost: 0.,
-		}
-	}
+pub fn from_tile(tile: IVec2) -> Vec2 {
+	tile.as_vec2() * T_SIZE
 }
 
-impl PartialEq for Node<'_> {
-	fn eq(&self, other: &Self) -> bool {
-		self.pos == other.pos
-	}
+// Octile distance, which never overestimates when diagonals cost DIAGONAL_COST
+fn heuristic(a: IVec2, b: IVec2) -> i32 {
+	let d = (a - b).abs();
+	STRAIGHT_COST * d.max_element() + (DIAGONAL_COST - STRAIGHT_COST) * d.min_element()
 }
 
-fn get_path_from_node(node: &Node) -> Vec<Vec2> {
-	let mut path = Vec::<Vec2>::new();
+// Rock, solid structures and unloaded chunks all block
+pub fn is_passable(world: &Level, pos: Vec2) -> bool {
+	world.is_loaded(pos)
+		&& !world.is_wall_at(pos)
+		&& !world.structure_at(pos + T_SIZE / 2.).map(|s| s.collides()).unwrap_or(false)
+}
 
-	let mut next_node = node;
-	loop {
-		path.push(next_node.pos);
-		if let Some(p) = next_node.parent {
-			next_node = p;
-		} else {
-			break;
-		}
+// Whether `to` is one step from `from`, going by the same corner rule as the paths
+pub fn within_reach(world: &Level, from: Vec2, to: Vec2) -> bool {
+	let start = to_tile(from);
+	let step = to_tile(to) - start;
+
+	if step.abs().max_element() != 1 {
+		return false;
 	}
 
-	path
+	step.x == 0 || step.y == 0 || (
+		is_passable(world, from_tile(start + ivec2(step.x, 0)))
+			&& is_passable(world, from_tile(start + ivec2(0, step.y)))
+	)
+}
+
+// Path from `start_pos` to `end_pos` as the top left of every tile along the way, without the
+// start. Diagonal steps aren't allowed to cut past a blocked corner.
+pub fn astar(world: &Level, start_pos: Vec2, end_pos: Vec2) -> Option<Vec<Vec2>> {
+	astar_by(start_pos, end_pos, |tile| is_passable(world, from_tile(tile)).then_some(0))
 }
 
-fn is_invalid_pos(chunk: &Chunk, node: &Node) -> bool {
-	if chunk.colliders.iter().position(|w: &Wall| w.rect.point() == node.pos).is_some() {
-		return true;
+// Like `astar`, but `extra_cost` decides what can be gone through. It gives what entering a
+// tile costs on top of the step itself, or None where there's no way through at all. Only
+// tiles that cost nothing extra count as open corners for diagonal steps.
+pub fn astar_by(start_pos: Vec2, end_pos: Vec2, extra_cost: impl Fn(IVec2) -> Option<i32>) -> Option<Vec<Vec2>> {
+	let start = to_tile(start_pos);
+	let end = to_tile(end_pos);
+
+	if start == end {
+		return Some(Vec::new());
 	}
 
-	false
-}
+	let mut costs = HashMap::<IVec2, Option<i32>>::new();
+	let mut cost_of = |tile: IVec2| {
+		*costs.entry(tile).or_insert_with(|| extra_cost(tile))
+	};
 
-fn get_sqr_dist(first: Vec2, second: Vec2) -> f32 {
-	let sqr_x_dist = (first.x - second.x).powf(2.);
-	let sqr_y_dist = (first.y - second.y).powf(2.);
+	cost_of(end)?;
 
-	sqr_x_dist + sqr_y_dist
-}
+	let mut open = BinaryHeap::new();
+	let mut closed = HashSet::<IVec2>::new();
+	let mut came_from = HashMap::<IVec2, IVec2>::new();
+	let mut cost_so_far = HashMap::<IVec2, i32>::new();
 
-pub fn astar(chunk: &Chunk, start_pos: Vec2, end_pos: Vec2) -> Vec<Vec2> {
-	let start_node = Node::new(start_pos, None);
-	let end_node = Node::new(end_pos, None);
+	open.push(Reverse((heuristic(start, end), start.x, start.y)));
+	cost_so_far.insert(start, 0);
 
-	let mut nodes = vec![start_node];
-	let mut visited = Vec::<Vec2>::new();
+	while let Some(Reverse((_, x, y))) = open.pop() {
+		let current = ivec2(x, y);
 
-	let dist = start_pos.distance(end_pos);
+		if current == end {
+			let mut path = vec![from_tile(end)];
+			let mut tile = end;
 
-	while nodes.len() > 0 {
-		if nodes.len() as f32 > dist * 10. {
-			return Vec::new();
-		}
+			while let Some(&previous) = came_from.get(&tile) {
+				if previous == start {
+					break;
+				}
 
-		let mut current_index = 0;
-		for i in 0..nodes.len() {
-			if nodes[i].cost < nodes[current_index].cost {
-				current_index = i;
+				path.push(from_tile(previous));
+				tile = previous;
 			}
+
+			path.reverse();
+			return Some(path);
 		}
 
-		visited.push(nodes[current_index].pos);
+		// Stale heap entries for tiles that were already reached more cheaply
+		if !closed.insert(current) {
+			continue;
+		}
 
-		if nodes[current_index] == end_node {
-			return get_path_from_node(&nodes[current_index])[1..].to_vec();
+		if closed.len() > MAX_SEARCH {
+			return None;
 		}
 
-		'adj_loop: for new_pos in adj_8_t() {
-			let node_pos = nodes[current_index].pos + new_pos;
-			let dist_from_start = nodes[current_index].dist_from_start + 1.;
-			let dist_from_target = get_sqr_dist(node_pos, end_node.pos);
-			let parent = &(nodes[current_index]) as *const Node;
-
-			unsafe {
-				nodes.push(Node {
-					pos: node_pos,
-					parent: Some(&*parent as &Node),
-					dist_from_start,
-					dist_from_target,
-					cost: dist_from_start + dist_from_target,
-				});
-			}
-			let new_node = nodes.last().unwrap();
+		for offset in adj_8_t() {
+			let step = (offset / T_SIZE).round().as_ivec2();
+			let next = current + step;
 
-			if is_invalid_pos(chunk, &new_node) {
+			if closed.contains(&next) {
 				continue;
 			}
 
-			if visited.contains(&new_node.pos) {
+			let Some(extra) = cost_of(next) else {
+				continue;
+			};
+
+			let diagonal = step.x != 0 && step.y != 0;
+
+			if diagonal && (cost_of(current + ivec2(step.x, 0)) != Some(0) || cost_of(current + ivec2(0, step.y)) != Some(0)) {
 				continue;
 			}
 
-			for node in &nodes {
-				if *new_node == *node && new_node.dist_from_start > node.dist_from_start {
-					continue 'adj_loop;
-				}
+			let cost = cost_so_far[&current] + extra + if diagonal { DIAGONAL_COST } else { STRAIGHT_COST };
+
+			if cost_so_far.get(&next).map(|&c| cost < c).unwrap_or(true) {
+				cost_so_far.insert(next, cost);
+				came_from.insert(next, current);
+				open.push(Reverse((cost + heuristic(next, end), next.x, next.y)));
 			}
 		}
 	}
 
-	Vec::new()
+	None
 }
 
-pub fn shite_step(chunk: &Chunk, start_pos: Vec2, end_pos: Vec2) -> Option<Vec2> {
-	let mut shortest = Option::<Vec2>::None;
-	let mut shortest_dist = Option::<f32>::None;
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::level::*;
+	use crate::test_utils::*;
 
-	for adj_pos in adj_8_t() {
-		let new_pos = adj_pos + start_pos;
-		let new_dist = new_pos.distance(end_pos);
-		
-		if chunk.colliders.iter().position(|w: &Wall| w.rect.point() == new_pos).is_some() {
-			continue;
+	fn open_level() -> Level {
+		let mut level = Level::new(1234);
+		clear(&mut level, 0, 0, 10, 10);
+		level
+	}
+
+	fn steps_are_adjacent(start: Vec2, path: &[Vec2]) {
+		let mut previous = start;
+
+		for &step in path {
+			assert!((to_tile(step) - to_tile(previous)).abs().max_element() == 1);
+			previous = step;
 		}
+	}
+
+	#[test]
+	fn straight_and_diagonal_paths_are_shortest() {
+		let level = open_level();
+
+		let path = astar(&level, tile(1, 1), tile(5, 1)).unwrap();
+		assert_eq!(path, vec![tile(2, 1), tile(3, 1), tile(4, 1), tile(5, 1)]);
 
-		if let Some(dist) = shortest_dist {
-			if new_dist < dist {
-				shortest = Some(new_pos);
-				shortest_dist = Some(new_dist);
-			}			
-		} else {
-			shortest = Some(new_pos);
-			shortest_dist = Some(new_dist);
+		let path = astar(&level, tile(1, 1), tile(4, 4)).unwrap();
+		assert_eq!(path.len(), 3);
+		assert_eq!(*path.last().unwrap(), tile(4, 4));
+	}
+
+	#[test]
+	fn paths_go_around_walls() {
+		let mut level = open_level();
+
+		for y in 0..8 {
+			put_wall(&mut level, 4, y);
+		}
+
+		let path = astar(&level, tile(2, 2), tile(6, 2)).unwrap();
+		steps_are_adjacent(tile(2, 2), &path);
+
+		for step in &path {
+			assert!(!level.is_wall_at(*step));
 		}
+
+		assert_eq!(*path.last().unwrap(), tile(6, 2));
+	}
+
+	#[test]
+	fn diagonals_do_not_cut_corners() {
+		let mut level = open_level();
+		put_wall(&mut level, 2, 1);
+
+		// Going from (1, 1) to (2, 2) diagonally would squeeze past the wall at (2, 1)
+		let path = astar(&level, tile(1, 1), tile(3, 1)).unwrap();
+		steps_are_adjacent(tile(1, 1), &path);
+		assert!(!path.contains(&tile(2, 1)));
+		assert_eq!(path.len(), 4);
+
+		put_wall(&mut level, 1, 2);
+		let path = astar(&level, tile(1, 1), tile(2, 2)).unwrap();
+		assert!(path.len() > 1);
+	}
+
+	#[test]
+	fn reach_does_not_go_round_corners() {
+		let mut level = open_level();
+
+		assert!(within_reach(&level, tile(1, 1), tile(2, 2)));
+		assert!(within_reach(&level, tile(1, 1), tile(1, 2)));
+		assert!(!within_reach(&level, tile(1, 1), tile(1, 1)));
+		assert!(!within_reach(&level, tile(1, 1), tile(3, 1)));
+
+		put_wall(&mut level, 2, 1);
+		assert!(!within_reach(&level, tile(1, 1), tile(2, 2)));
+		assert!(within_reach(&level, tile(1, 1), tile(1, 2)));
 	}
 
-	shortest
-}
\ No newline at end of file
+	#[test]
+	fn walled_in_targets_have_no_path() {
+		let mut level = open_level();
+
+		for (x, y) in [(4, 4), (5, 4), (6, 4), (4, 5), (6, 5), (4, 6), (5, 6), (6, 6)] {
+			put_wall(&mut level, x, y);
+		}
+
+		assert_eq!(astar(&level, tile(1, 1), tile(5, 5)), None);
+		assert_eq!(astar(&level, tile(1, 1), tile(4, 4)), None);
+	}
+
+	#[test]
+	fn solid_structures_block_paths() {
+		let mut level = open_level();
+
+		for y in 0..Chunk::SIZE as i32 {
+			place_steel_wall(&mut level, 4, y);
+		}
+
+		assert!(!is_passable(&level, tile(4, 3)));
+		assert_eq!(astar(&level, tile(2, 3), tile(6, 3)), None);
+	}
+
+	#[test]
+	fn costly_tiles_are_gone_through_when_there_is_no_way_round() {
+		let mut level = open_level();
+
+		for y in 0..Chunk::SIZE as i32 {
+			put_wall(&mut level, 4, y);
+		}
+
+		assert_eq!(astar(&level, tile(2, 3), tile(6, 3)), None);
+
+		let through_rock = |tile: IVec2| if level.is_wall_at(from_tile(tile)) { Some(50) } else { Some(0) };
+		let path = astar_by(tile(2, 3), tile(6, 3), through_rock).unwrap();
+		assert_eq!(path, vec![tile(3, 3), tile(4, 3), tile(5, 3), tile(6, 3)]);
+	}
+}
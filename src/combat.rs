@@ -0,0 +1,127 @@
+use ::rand::{Rng, rngs::StdRng};
+
+use crate::primitives::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Faction {
+	Dwarves,
+	Kobolds,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Stats {
+	// Chance to land a blow, 0 to 1
+	pub accuracy: f64,
+	pub min_damage: i32,
+	pub max_damage: i32,
+	// Taken off the damage of every hit
+	pub armor: i32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Hit {
+	Miss,
+	// Connected, but the armor took all of it
+	Absorbed,
+	Wounded(i32),
+	Killed(i32),
+}
+
+pub fn hostile(a: Faction, b: Faction) -> bool {
+	a != b
+}
+
+pub fn attack(attacker: Stats, defender: &mut Box<dyn Entity>, rng: &mut StdRng) -> Hit {
+	if !rng.gen_bool(attacker.accuracy) {
+		return Hit::Miss;
+	}
+
+	let damage = rng.gen_range(attacker.min_damage..=attacker.max_damage) - defender.stats().armor;
+
+	if damage <= 0 {
+		Hit::Absorbed
+	} else if defender.hurt(damage) {
+		Hit::Killed(damage)
+	} else {
+		Hit::Wounded(damage)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ::rand::SeedableRng;
+	use macroquad::prelude::*;
+
+	use super::*;
+	use crate::kobold::*;
+	use crate::player::*;
+	use crate::entities::*;
+	use crate::generation::*;
+	use crate::items::*;
+
+	const SURE_HIT: Stats = Stats {
+		accuracy: 1.,
+		min_damage: 3,
+		max_damage: 3,
+		armor: 0,
+	};
+
+	#[test]
+	fn armor_soaks_up_damage() {
+		let mut rng = StdRng::seed_from_u64(0);
+		let mut player = Box::new(Player::new(Vec2::ZERO)) as Box<dyn Entity>;
+
+		let hit = attack(SURE_HIT, &mut player, &mut rng);
+		assert_eq!(hit, Hit::Wounded(3 - Player::STATS.armor));
+		assert_eq!(player.hp(), Player::MAX_HP - 3 + Player::STATS.armor);
+
+		let weak = Stats {
+			max_damage: 1,
+			min_damage: 1,
+			..SURE_HIT
+		};
+		assert_eq!(attack(weak, &mut player, &mut rng), Hit::Absorbed);
+	}
+
+	#[test]
+	fn missing_never_hurts() {
+		let mut rng = StdRng::seed_from_u64(0);
+		let mut kobold = Box::new(Kobold::new(Vec2::ZERO)) as Box<dyn Entity>;
+		let blind = Stats {
+			accuracy: 0.,
+			..SURE_HIT
+		};
+
+		for _ in 0..20 {
+			assert_eq!(attack(blind, &mut kobold, &mut rng), Hit::Miss);
+		}
+
+		assert_eq!(kobold.hp(), Kobold::MAX_HP);
+	}
+
+	#[test]
+	fn the_dead_are_removed_and_drop_their_things() {
+		let mut level = Level::new(1234);
+		let mut entities = Entities::new();
+		let mut rng = StdRng::seed_from_u64(0);
+
+		let mut player = Player::new(Vec2::ZERO);
+		player.inventory.add(ItemStack::new(ItemType::Stone, 3));
+		let id = entities.spawn(Box::new(player));
+		entities.flush();
+
+		let target = entities.get_mut(id).unwrap();
+		let mut hit = Hit::Miss;
+		while !matches!(hit, Hit::Killed(_)) {
+			hit = attack(SURE_HIT, target, &mut rng);
+		}
+
+		assert!(target.dead());
+
+		entities.reap(&mut level);
+		entities.flush();
+
+		assert!(entities.get(id).is_none());
+		assert_eq!(level.take_items_at(Vec2::ZERO), vec![ItemStack::new(ItemType::Stone, 3)]);
+	}
+}
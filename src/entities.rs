@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use macroquad::prelude::*;
+
+use crate::primitives::*;
+use crate::generation::*;
+
+// Owns every entity under a stable id. Spawning and despawning are queued and only happen on
+// `flush`, so nothing moves out from under whoever is iterating.
+pub struct Entities {
+	entities: HashMap<usize, Box<dyn Entity>>,
+	// Ids in spawn order, so updates and drawing don't depend on hashing
+	order: Vec<usize>,
+	next_id: usize,
+
+	spawn_queue: Vec<Box<dyn Entity>>,
+	despawn_queue: Vec<usize>,
+}
+
+impl Entities {
+	pub fn new() -> Self {
+		Self {
+			entities: HashMap::new(),
+			order: Vec::new(),
+			next_id: 0,
+
+			spawn_queue: Vec::new(),
+			despawn_queue: Vec::new(),
+		}
+	}
+
+	// The id is handed out straight away, but the entity only shows up after the next flush
+	pub fn spawn(&mut self, mut entity: Box<dyn Entity>) -> usize {
+		let id = self.next_id;
+		self.next_id += 1;
+
+		entity.set_id(id);
+		self.spawn_queue.push(entity);
+
+		id
+	}
+
+	pub fn despawn(&mut self, id: usize) {
+		if !self.despawn_queue.contains(&id) {
+			self.despawn_queue.push(id);
+		}
+	}
+
+	pub fn flush(&mut self) {
+		for id in self.despawn_queue.drain(..) {
+			self.entities.remove(&id);
+			self.order.retain(|&i| i != id);
+		}
+
+		for entity in self.spawn_queue.drain(..) {
+			self.order.push(entity.id());
+			self.entities.insert(entity.id(), entity);
+		}
+	}
+
+	// Queues the dead for removal, leaving their corpse and belongings where they fell
+	pub fn reap(&mut self, world: &mut Level) {
+		let dead = self.iter().filter(|e| e.dead()).map(|e| e.id()).collect::<Vec<usize>>();
+
+		for id in dead {
+			let Some(entity) = self.entities.get_mut(&id) else {
+				continue;
+			};
+
+			let pos = snap_to_tile(entity.rect().center());
+
+			if let Some(tex) = entity.corpse() {
+				world.add_corpse(pos, tex);
+			}
+
+			for stack in entity.drops() {
+				world.drop_item(pos, stack);
+			}
+
+			self.despawn(id);
+		}
+	}
+
+	pub fn ids(&self) -> Vec<usize> {
+		self.order.clone()
+	}
+
+	pub fn get(&self, id: usize) -> Option<&dyn Entity> {
+		self.entities.get(&id).map(|e| e.as_ref())
+	}
+
+	pub fn get_mut(&mut self, id: usize) -> Option<&mut Box<dyn Entity>> {
+		self.entities.get_mut(&id)
+	}
+
+	// Lends an entity out so it can be updated with access to everyone else. It has to be
+	// handed back with `put_back`, and lookups won't find it in the meantime.
+	pub fn take(&mut self, id: usize) -> Option<Box<dyn Entity>> {
+		self.entities.remove(&id)
+	}
+
+	pub fn put_back(&mut self, entity: Box<dyn Entity>) {
+		self.entities.insert(entity.id(), entity);
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &Box<dyn Entity>> {
+		self.order.iter().filter_map(|id| self.entities.get(id))
+	}
+
+	pub fn player_id(&self) -> Option<usize> {
+		self.iter().find(|e| matches!(e.data(), EntityData::Player {..})).map(|e| e.id())
+	}
+
+	// Whoever is standing on the tile containing `pos`
+	pub fn at(&self, pos: Vec2) -> Option<usize> {
+		self.iter().find(|e| e.rect().contains(pos)).map(|e| e.id())
+	}
+
+	pub fn nearest(&self, pos: Vec2, filter: impl Fn(&dyn Entity) -> bool) -> Option<usize> {
+		self.iter()
+			.filter(|e| filter(e.as_ref()))
+			.min_by(|a, b| {
+				let a = a.rect().center().distance_squared(pos);
+				let b = b.rect().center().distance_squared(pos);
+				a.total_cmp(&b)
+			})
+			.map(|e| e.id())
+	}
+}
@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+
+use macroquad::prelude::*;
+
+// Turns the first octant's (column, row) into world offsets for each of the eight
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+	(1, 0, 0, 1),
+	(0, 1, 1, 0),
+	(0, -1, 1, 0),
+	(-1, 0, 0, 1),
+	(-1, 0, 0, -1),
+	(0, -1, -1, 0),
+	(0, 1, -1, 0),
+	(1, 0, 0, -1),
+];
+
+struct Shadowcaster<F: Fn(IVec2) -> bool> {
+	origin: IVec2,
+	radius: i32,
+	blocks: F,
+	visible: HashSet<IVec2>,
+}
+
+impl<F: Fn(IVec2) -> bool> Shadowcaster<F> {
+	// Scans one octant row by row outward, between the `start` and `end` slopes. Every run of
+	// blockers splits the light, with the part before it carried on by a recursive scan.
+	fn cast(&mut self, row: i32, mut start: f32, end: f32, octant: (i32, i32, i32, i32)) {
+		if start < end {
+			return;
+		}
+
+		let (xx, xy, yx, yy) = octant;
+		let mut new_start = 0.;
+
+		for j in row..=self.radius {
+			let dy = -j;
+			let mut blocked = false;
+
+			for dx in -j..=0 {
+				let left = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+				let right = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+
+				if start < right {
+					continue;
+				}
+
+				if end > left {
+					break;
+				}
+
+				let tile = self.origin + ivec2(dx * xx + dy * xy, dx * yx + dy * yy);
+
+				if dx * dx + dy * dy <= self.radius * self.radius {
+					self.visible.insert(tile);
+				}
+
+				let opaque = (self.blocks)(tile);
+
+				if blocked {
+					if opaque {
+						new_start = right;
+						continue;
+					}
+
+					blocked = false;
+					start = new_start;
+				} else if opaque && j < self.radius {
+					blocked = true;
+					self.cast(j + 1, start, left, octant);
+					new_start = right;
+				}
+			}
+
+			if blocked {
+				break;
+			}
+		}
+	}
+}
+
+// Recursive shadowcasting. Every tile within `radius` of `origin` that a straight line can
+// reach, including the blockers themselves, so walls facing the viewer are lit.
+pub fn shadowcast(origin: IVec2, radius: i32, blocks: impl Fn(IVec2) -> bool) -> HashSet<IVec2> {
+	let mut caster = Shadowcaster {
+		origin,
+		radius,
+		blocks,
+		visible: HashSet::from([origin]),
+	};
+
+	for octant in OCTANTS {
+		caster.cast(1, 1., 0., octant);
+	}
+
+	caster.visible
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn open_ground_is_seen_out_to_the_radius() {
+		let visible = shadowcast(IVec2::ZERO, 5, |_| false);
+
+		for x in -5..=5 {
+			for y in -5..=5 {
+				assert_eq!(visible.contains(&ivec2(x, y)), x * x + y * y <= 25, "{x}, {y}");
+			}
+		}
+	}
+
+	#[test]
+	fn walls_cast_shadows() {
+		let wall = |tile: IVec2| tile.x == 2 && (-1..=1).contains(&tile.y);
+		let visible = shadowcast(IVec2::ZERO, 8, wall);
+
+		assert!(visible.contains(&ivec2(2, 0)));
+		assert!(visible.contains(&ivec2(2, 1)));
+
+		for x in 3..=6 {
+			assert!(!visible.contains(&ivec2(x, 0)));
+		}
+
+		assert!(visible.contains(&ivec2(-6, 0)));
+		assert!(visible.contains(&ivec2(0, 6)));
+	}
+
+	#[test]
+	fn closed_rooms_hide_everything_outside() {
+		let walls = |tile: IVec2| tile.x.abs().max(tile.y.abs()) == 2;
+		let visible = shadowcast(IVec2::ZERO, 10, walls);
+
+		assert!(visible.iter().all(|t| t.x.abs().max(t.y.abs()) <= 2));
+		assert_eq!(visible.len(), 25);
+	}
+}
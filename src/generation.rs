@@ -1,112 +1,848 @@
-use std::collections::HashMap;
-use ::rand::Rng;
-use maplit::hashmap;
+use std::collections::{HashMap, HashSet};
+use ::rand::{SeedableRng, rngs::StdRng};
 use macroquad::prelude::*;
 
-use crate::components::*;
+use hot_assets::*;
 
-fn noise(x: f32, y: f32) -> f32 {
-	let mut rng = ::rand::thread_rng();
+use crate::level::*;
+use crate::items::*;
+use crate::primitives::*;
+use crate::types::*;
+use crate::power::*;
+use crate::structures::*;
+use crate::spawning::*;
+use crate::fov::*;
+use crate::a_star::*;
 
-	(x as f32 + rng.gen_range(0_f32..5_f32)).cos() * rng.gen_range(1_f32..10_f32) - (y as f32 + rng.gen_range(0_f32..5_f32)).sin() * rng.gen_range(1_f32..10_f32)
+// splitmix64, so neighbouring tiles don't end up with related values
+fn hash(mut x: u64) -> u64 {
+	x = x.wrapping_add(0x9E3779B97F4A7C15);
+	x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+	x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+	x ^ (x >> 31)
 }
 
-#[derive(Clone)]
-pub struct Tile {
-	hardness: i16,
-	sprite: CharSprite,
+pub fn tile_hash(salt: u64, tile: IVec2) -> u64 {
+	hash(salt ^ hash(((tile.x as u32 as u64) << 32) | tile.y as u32 as u64))
 }
 
-#[derive(Clone)]
-pub struct Chunk {
-	pos: I16Vec2,
-	tiles: HashMap<I16Vec2, Tile>,
+// Numbers are used as is, anything else gets hashed so seeds can be words too
+pub fn parse_seed(text: &str) -> u64 {
+	if let Ok(seed) = text.trim().parse::<u64>() {
+		return seed;
+	}
+
+	text.bytes().fold(0, |acc, b| hash(acc ^ b as u64))
+}
+
+pub struct Level {
+	pub chunks: HashMap<I16Vec2, Chunk>,
+	pub seed: u64,
+	pub power_grids: Vec<PowerGrid>,
+	// For rolls that don't belong to any one chunk, like combat
+	pub rng: StdRng,
+	// Tiles in the player's view right now
+	pub visible: HashSet<IVec2>,
 }
 
-impl Chunk {
-	fn generate(pos: I16Vec2, size: I16Vec2) -> Self {
-		let mut tiles = HashMap::<I16Vec2, Tile>::new();
+impl Level {
+	// Share of the build cost dropped by a structure that gets destroyed
+	pub const DESTROYED_REFUND: f32 = 0.25;
+	// Share of the build cost handed back when the player takes a structure apart
+	pub const DECONSTRUCT_REFUND: f32 = 0.75;
+	// How close to a chunk edge (in tiles) something has to get before the neighbour is generated
+	pub const STREAM_DISTANCE: f32 = 16.;
+	// Chance out of 100 for a tile to start out as rock before smoothing
+	pub const FILL_PERCENT: u64 = 45;
 
-		for local_x in (0..size.x * T_SIZE).step_by(USIZE_T_SIZE) {
-			for local_y in (0..size.y * T_SIZE).step_by(USIZE_T_SIZE) {
-				let x = local_x + pos.x;
-				let y = local_y + pos.y;
+	pub fn new(seed: u64) -> Self {
+		let mut level = Self {
+			chunks: HashMap::new(),
+			seed,
+			power_grids: Vec::new(),
+			rng: StdRng::seed_from_u64(hash(seed ^ 0x5EED)),
+			visible: HashSet::new(),
+		};
 
-				let mut n = 0;
+		level.generate_chunk(I16Vec2::ZERO);
 
-				for adj in ADJACENT_8 {
-					let adj_tile = adj.as_vec2() + vec2(x as f32, y as f32);
+		level
+	}
 
-					if noise(adj_tile.x, adj_tile.y) > 0.01 {
-						n += 1;
-					}
+	pub fn chunk_pos_at(pos: Vec2) -> I16Vec2 {
+		(pos / Chunk::PIXEL_SIZE).floor().as_i16vec2()
+	}
+
+	// The starting noise is a pure function of the world tile, so it's the same whichever chunk samples it
+	pub fn is_rock(seed: u64, tile: IVec2) -> bool {
+		tile_hash(seed, tile) % 100 < Self::FILL_PERCENT
+	}
+
+	// Each chunk gets its own rng derived from the seed and its coordinate, so the order chunks
+	// are generated in doesn't change what's in them
+	pub fn chunk_seed(seed: u64, chunk_pos: I16Vec2) -> u64 {
+		tile_hash(hash(seed), chunk_pos.as_ivec2())
+	}
+
+	pub fn generate_chunk(&mut self, chunk_pos: I16Vec2) {
+		if self.chunks.contains_key(&chunk_pos) {
+			return;
+		}
+
+		let seed = self.seed;
+
+		let mut chunk = Chunk::new(chunk_pos, Self::chunk_seed(seed, chunk_pos));
+		chunk.generate(|tile| Self::is_rock(seed, tile));
+		self.chunks.insert(chunk_pos, chunk);
+	}
+
+	pub fn is_loaded(&self, pos: Vec2) -> bool {
+		self.chunks.contains_key(&Self::chunk_pos_at(pos))
+	}
+
+	pub fn get_chunk(&self, pos: Vec2) -> Option<&Chunk> {
+		self.chunks.get(&Self::chunk_pos_at(pos))
+	}
+
+	pub fn get_chunk_mut(&mut self, pos: Vec2) -> Option<&mut Chunk> {
+		self.chunks.get_mut(&Self::chunk_pos_at(pos))
+	}
+
+	pub fn wall_at(&self, pos: Vec2) -> Option<&Wall> {
+		let chunk = self.get_chunk(pos)?;
+		chunk.wall_index_at(pos).map(|i| &chunk.colliders[i])
+	}
+
+	pub fn is_wall_at(&self, pos: Vec2) -> bool {
+		self.wall_at(pos).is_some()
+	}
+
+	pub fn damage_terrain(&mut self, pos: Vec2, amount: f32) {
+		if let Some(chunk) = self.get_chunk_mut(pos) {
+			if let Some(i) = chunk.wall_index_at(pos) {
+				chunk.damage_terrain(i, amount);
+			}
+		}
+	}
+
+	pub fn drop_item(&mut self, pos: Vec2, stack: ItemStack) {
+		if let Some(chunk) = self.get_chunk_mut(pos) {
+			chunk.drop_item(pos, stack);
+		}
+	}
+
+	pub fn take_items_at(&mut self, pos: Vec2) -> Vec<ItemStack> {
+		if let Some(chunk) = self.get_chunk_mut(pos) {
+			chunk.take_items_at(pos)
+		} else {
+			Vec::new()
+		}
+	}
+
+	pub fn items(&self) -> impl Iterator<Item = &FloorItem> {
+		self.chunks.values().flat_map(|c| c.items.iter())
+	}
+
+	pub fn add_corpse(&mut self, pos: Vec2, tex: &str) {
+		if let Some(chunk) = self.get_chunk_mut(pos) {
+			chunk.add_corpse(pos, tex);
+		}
+	}
+
+	// Every lair monster still waiting to be brought in, leaving the chunks empty-handed
+	pub fn take_lair_spawns(&mut self) -> Vec<(MonsterType, Vec2)> {
+		self.chunks.values_mut().flat_map(|c| std::mem::take(&mut c.lairs)).collect()
+	}
+
+	pub fn walls_overlapping(&self, rect: Rect) -> impl Iterator<Item = &Wall> {
+		self.chunks.values()
+			.filter(move |c| c.bounds().overlaps(&rect))
+			.flat_map(move |c| c.colliders.iter().filter(move |w| w.rect.overlaps(&rect)))
+	}
+
+	pub fn structures(&self) -> impl Iterator<Item = &Box<dyn Structure>> {
+		self.chunks.values().flat_map(|c| c.structures.iter())
+	}
+
+	pub fn structures_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn Structure>> {
+		self.chunks.values_mut().flat_map(|c| c.structures.iter_mut())
+	}
+
+	pub fn structure_at(&self, pos: Vec2) -> Option<&dyn Structure> {
+		self.structures().find(|s| s.rect().contains(pos)).map(|s| s.as_ref())
+	}
+
+	pub fn structure_at_mut(&mut self, pos: Vec2) -> Option<&mut Box<dyn Structure>> {
+		self.structures_mut().find(|s| s.rect().contains(pos))
+	}
+
+	// Rock and solid structures can't be seen through
+	pub fn blocks_sight(&self, pos: Vec2) -> bool {
+		self.is_wall_at(snap_to_tile(pos))
+			|| self.structure_at(snap_to_tile(pos) + T_SIZE / 2.).map(|s| s.collides()).unwrap_or(false)
+	}
+
+	// Samples the tiles between `from` and `to` once each. Neither end counts, so whatever
+	// stands at `to` can still be seen even if it's solid itself. Slipping diagonally between
+	// two blockers doesn't count either, just like a path can't.
+	pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+		let steps = ((to - from).abs().max_element() / T_SIZE).ceil() as i32;
+		let end = snap_to_tile(to);
+		let target = self.structure_at(to).map(|s| s.rect());
+		let blocks = |pos: Vec2| {
+			!target.map(|r| r.contains(pos + T_SIZE / 2.)).unwrap_or(false) && self.blocks_sight(pos)
+		};
+
+		let mut previous = snap_to_tile(from);
+
+		for i in 1..=steps {
+			let tile = snap_to_tile(from.lerp(to, i as f32 / steps as f32));
+			let step = tile - previous;
+
+			if step.x != 0. && step.y != 0. && blocks(previous + vec2(step.x, 0.)) && blocks(previous + vec2(0., step.y)) {
+				return false;
+			}
+
+			if tile != end && blocks(tile) {
+				return false;
+			}
+
+			previous = tile;
+		}
+
+		true
+	}
+
+	// Works out what can be seen from `pos` and remembers all of it as seen. Unloaded chunks
+	// block sight like rock does.
+	pub fn update_fov(&mut self, pos: Vec2, radius: i32) {
+		let visible = shadowcast(to_tile(pos), radius, |tile| {
+			!self.is_loaded(from_tile(tile)) || self.blocks_sight(from_tile(tile))
+		});
+		let before = std::mem::replace(&mut self.visible, visible);
+
+		// Only tiles coming into or going out of view change how they're fogged
+		for &tile in before.symmetric_difference(&self.visible) {
+			if let Some(chunk) = self.chunks.get_mut(&Self::chunk_pos_at(from_tile(tile))) {
+				chunk.fog_stale = true;
+			}
+		}
+
+		for &tile in &self.visible {
+			let pos = from_tile(tile);
+
+			if let Some(chunk) = self.chunks.get_mut(&Self::chunk_pos_at(pos)) {
+				let (x, y) = chunk.local_tile(pos);
+				chunk.seen[y][x] = true;
+			}
+		}
+	}
+
+	pub fn is_visible(&self, pos: Vec2) -> bool {
+		self.visible.contains(&to_tile(pos))
+	}
+
+	pub fn is_seen(&self, pos: Vec2) -> bool {
+		self.get_chunk(pos)
+			.map(|c| {
+				let (x, y) = c.local_tile(pos);
+				c.seen[y][x]
+			})
+			.unwrap_or(false)
+	}
+
+	// Structures belong to the chunk their top left corner is in, even if they hang over the edge
+	pub fn add_structure(&mut self, structure: Box<dyn Structure>) {
+		let chunk_pos = Self::chunk_pos_at(structure.rect().point());
+		self.generate_chunk(chunk_pos);
+
+		if let Some(chunk) = self.chunks.get_mut(&chunk_pos) {
+			chunk.structures.push(structure);
+		}
+	}
+
+	// Make sure every chunk the given position is getting close to exists
+	pub fn stream(&mut self, pos: Vec2) {
+		let chunk_pos = Self::chunk_pos_at(pos);
+		let local = (pos - chunk_pos.as_vec2() * Chunk::PIXEL_SIZE) / T_SIZE;
+		let far_edge = Chunk::SIZE as f32 - Self::STREAM_DISTANCE;
+
+		let dx = if local.x < Self::STREAM_DISTANCE {
+			-1
+		} else if local.x >= far_edge {
+			1
+		} else {
+			0
+		};
+
+		let dy = if local.y < Self::STREAM_DISTANCE {
+			-1
+		} else if local.y >= far_edge {
+			1
+		} else {
+			0
+		};
+
+		self.generate_chunk(chunk_pos);
+		self.generate_chunk(chunk_pos + i16vec2(dx, 0));
+		self.generate_chunk(chunk_pos + i16vec2(0, dy));
+		self.generate_chunk(chunk_pos + i16vec2(dx, dy));
+	}
+
+	pub fn draw(&mut self, camera: &Camera2D, assets: &AssetManager) {
+		let top_left = camera.screen_to_world(vec2(0., 0.));
+		let bottom_right = camera.screen_to_world(vec2(screen_width(), screen_height()));
+		let view = Rect::new(
+			top_left.x.min(bottom_right.x), top_left.y.min(bottom_right.y),
+			(bottom_right.x - top_left.x).abs(), (bottom_right.y - top_left.y).abs(),
+		);
+
+		// Baking switches to each chunk's render target, so it has to be done before drawing anything
+		for chunk in self.chunks.values_mut() {
+			if chunk.bounds().overlaps(&view) {
+				chunk.bake(assets);
+				chunk.bake_fog(&self.visible);
+			}
+		}
+
+		set_camera(camera);
+
+		for chunk in self.chunks.values() {
+			if chunk.bounds().overlaps(&view) {
+				chunk.draw();
+			}
+		}
+
+		for chunk in self.chunks.values() {
+			if chunk.bounds().overlaps(&view) {
+				chunk.draw_items(assets);
+			}
+		}
+
+		// Structures go on top of every chunk so ones hanging over an edge aren't covered by the neighbour.
+		// They're culled one by one, since one can be in view while the chunk it belongs to isn't.
+		for chunk in self.chunks.values_mut() {
+			chunk.draw_structures(view, assets);
+		}
+
+		// Anything outside the loaded chunks is left to the black background
+		for chunk in self.chunks.values() {
+			if chunk.bounds().overlaps(&view) {
+				chunk.draw_fog();
+			}
+		}
+	}
+
+	// Advances everything in the world that isn't an entity by one turn
+	pub fn tick(&mut self) {
+		let mut structures = self.structures_mut().collect::<Vec<&mut Box<dyn Structure>>>();
+		let power_grids = build_grids(&mut structures);
+
+		for structure in structures {
+			structure.tick();
+		}
+
+		self.power_grids = power_grids;
+		self.move_items();
+
+		let mut destroyed = Vec::<Box<dyn Structure>>::new();
+
+		for chunk in self.chunks.values_mut() {
+			let (dead, alive) = std::mem::take(&mut chunk.structures).into_iter()
+				.partition::<Vec<Box<dyn Structure>>, _>(|s| s.dead());
+			chunk.structures = alive;
+			destroyed.extend(dead);
+		}
+
+		for structure in destroyed {
+			let drop_pos = snap_to_tile(structure.rect().center());
+			self.scrap_structure(structure, drop_pos, Self::DESTROYED_REFUND, true);
+		}
+	}
+
+	// Every output port hands its items to the input port facing it, one tile per turn. Belts
+	// also scoop up whatever is lying on them and tip their load onto the floor at the end of a line.
+	fn move_items(&mut self) {
+		let ports = self.structures().flat_map(|s| s.ports()).collect::<Vec<Port>>();
+
+		let belts = self.structures()
+			.filter(|s| s.slots().iter().any(|slot| slot.kind == SlotKind::Belt && slot.stack.is_none()))
+			.map(|s| snap_to_tile(s.rect().center()))
+			.collect::<Vec<Vec2>>();
+
+		for tile in belts {
+			for stack in self.take_items_at(tile) {
+				let rest = self.structure_at_mut(tile + T_SIZE / 2.).and_then(|s| insert_into(s, stack));
+
+				if let Some(rest) = rest {
+					self.drop_item(tile, rest);
 				}
+			}
+		}
+
+		// Whatever arrives somewhere this turn stays there until the next one
+		let mut received = Vec::<Vec2>::new();
 
-				if n > 4 {
-					tiles.insert(i16vec2(x, y), Tile::new(n, '#', WHITE));
-				} else {
-					tiles.remove(&i16vec2(x, y));
+		for port in ports.iter().filter(|p| p.kind == PortKind::Output) {
+			if received.contains(&port.tile) {
+				continue;
+			}
+
+			let Some(source) = self.structure_at_mut(port.tile + T_SIZE / 2.) else {
+				continue;
+			};
+
+			let Some(slot_index) = source.slots().iter()
+				.position(|s| (s.kind == SlotKind::Output || s.kind == SlotKind::Belt) && s.stack.is_some()) else {
+				continue;
+			};
+
+			let from_belt = source.slots()[slot_index].kind == SlotKind::Belt;
+			let Some(stack) = source.slots_mut()[slot_index].take(i32::MAX) else {
+				continue;
+			};
+
+			let facing = ports.iter().any(|p| {
+				p.kind == PortKind::Input && p.tile == port.outside && p.outside == port.tile
+			});
+
+			let rest = if facing {
+				let rest = self.structure_at_mut(port.outside + T_SIZE / 2.).and_then(|s| insert_into(s, stack));
+
+				if rest != Some(stack) {
+					received.push(port.outside);
+				}
+
+				rest
+			} else if from_belt && !self.is_wall_at(port.outside) && self.structure_at(port.outside + T_SIZE / 2.).is_none() {
+				self.drop_item(port.outside, stack);
+				None
+			} else {
+				Some(stack)
+			};
+
+			if let Some(rest) = rest {
+				if let Some(source) = self.structure_at_mut(port.tile + T_SIZE / 2.) {
+					source.slots_mut()[slot_index].insert(rest);
 				}
 			}
 		}
+	}
+
+	pub fn remove_structure_at(&mut self, pos: Vec2) -> Option<Box<dyn Structure>> {
+		for chunk in self.chunks.values_mut() {
+			if let Some(i) = chunk.structures.iter().position(|s| s.rect().contains(pos)) {
+				return Some(chunk.structures.remove(i));
+			}
+		}
+
+		None
+	}
 
-		Self {
-			pos,
-			tiles,
+	// Takes the structure at `pos` apart, handing back most of what it cost
+	pub fn deconstruct(&mut self, pos: Vec2) -> bool {
+		if let Some(structure) = self.remove_structure_at(pos) {
+			self.scrap_structure(structure, snap_to_tile(pos), Self::DECONSTRUCT_REFUND, false);
+			true
+		} else {
+			false
 		}
 	}
 
-	pub fn draw(&self) {
-		draw_rectangle_lines(self.pos.x as f32, self.pos.y as f32, Level::CHUNK_PIXEL_SIZE as f32, Level::CHUNK_PIXEL_SIZE as f32, 5., RED);
+	// Drops part of the build cost and whatever was in the slots, optionally leaving rubble over the footprint
+	fn scrap_structure(&mut self, mut structure: Box<dyn Structure>, drop_pos: Vec2, refund: f32, rubble: bool) {
+		let rect = structure.rect();
+
+		if rubble {
+			let first = snap_to_tile(rect.point() + T_SIZE / 2.);
+			let columns = (rect.w / T_SIZE).round() as i32;
+			let rows = (rect.h / T_SIZE).round() as i32;
+
+			for x in 0..columns {
+				for y in 0..rows {
+					let tile = first + vec2(x as f32, y as f32) * T_SIZE;
+					if let Some(chunk) = self.get_chunk_mut(tile) {
+						chunk.add_rubble(tile);
+					}
+				}
+			}
+		}
+
+		for stack in structure.blueprint_type().refund(refund) {
+			self.drop_item(drop_pos, stack);
+		}
 
-		for (pos, tile) in self.tiles.iter() {
-			tile.sprite.draw((*pos).as_vec2());
+		for slot in structure.slots_mut() {
+			if let Some(stack) = slot.stack.take() {
+				self.drop_item(drop_pos, stack);
+			}
 		}
 	}
-}
 
-impl Tile {
-	pub fn new(hardness: i16, sprite: char, color: Color) -> Self {
-		Self {
-			hardness,
-			sprite: CharSprite::new(sprite, color),
+	pub fn draw_power_grids(&self) {
+		for grid in &self.power_grids {
+			grid.draw();
 		}
 	}
 }
 
-pub struct Level {
-	chunks: HashMap<I16Vec2, Chunk>,
-}
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_utils::*;
 
-impl Level {
-	pub const CHUNK_SIZE: i16 = 64;
-	pub const CHUNK_PIXEL_SIZE: i16 = Self::CHUNK_SIZE * T_SIZE;
+	const SEED: u64 = 1234;
+
+	fn wall_positions(chunk: &Chunk) -> Vec<Vec2> {
+		chunk.colliders.iter().map(|w| w.rect.point()).collect()
+	}
 
-	pub fn new() -> Self {
-		Self {
-			chunks: hashmap!{
-				i16vec2(0, 0) => Chunk::generate(i16vec2(0, 0), i16vec2(Self::CHUNK_SIZE, Self::CHUNK_SIZE)),
-			},
+	#[test]
+	fn same_seed_generates_the_same_chunk() {
+		let a = Level::new(SEED);
+		let b = Level::new(SEED);
+
+		let a = &a.chunks[&I16Vec2::ZERO];
+		let b = &b.chunks[&I16Vec2::ZERO];
+
+		assert_eq!(a.terrain, b.terrain);
+		assert_eq!(wall_positions(a), wall_positions(b));
+		assert_eq!(
+			a.items.iter().map(|i| (i.pos, i.stack)).collect::<Vec<_>>(),
+			b.items.iter().map(|i| (i.pos, i.stack)).collect::<Vec<_>>(),
+		);
+	}
+
+	#[test]
+	fn different_seeds_generate_different_chunks() {
+		let a = Level::new(1);
+		let b = Level::new(2);
+
+		assert_ne!(a.chunks[&I16Vec2::ZERO].terrain, b.chunks[&I16Vec2::ZERO].terrain);
+	}
+
+	#[test]
+	fn generation_order_does_not_matter() {
+		let mut a = Level::new(SEED);
+		a.generate_chunk(i16vec2(1, 0));
+		a.generate_chunk(i16vec2(2, 0));
+
+		let mut b = Level::new(SEED);
+		b.generate_chunk(i16vec2(2, 0));
+		b.generate_chunk(i16vec2(1, 0));
+
+		for pos in [i16vec2(1, 0), i16vec2(2, 0)] {
+			assert_eq!(a.chunks[&pos].terrain, b.chunks[&pos].terrain);
+			assert_eq!(wall_positions(&a.chunks[&pos]), wall_positions(&b.chunks[&pos]));
 		}
 	}
 
-	pub fn generate_chunk(&mut self, pos: I16Vec2) {
-		let snap_pos = (pos / Self::CHUNK_PIXEL_SIZE) * Self::CHUNK_PIXEL_SIZE;
-		if self.chunks.contains_key(&snap_pos) {
+	#[test]
+	fn caves_carry_on_across_chunk_edges() {
+		let mut level = Level::new(SEED);
+		level.generate_chunk(i16vec2(1, 0));
+		level.generate_chunk(i16vec2(2, 0));
 
-		} else {
-			self.chunks.insert(snap_pos, Chunk::generate(snap_pos, i16vec2(Self::CHUNK_SIZE, Self::CHUNK_SIZE)));
+		// A chunk sampled half a chunk further along straddles the seam between the two
+		let half = Chunk::SIZE / 2;
+		let mut straddling = Chunk::new(i16vec2(1, 0), 0);
+		straddling.generate(|tile| Level::is_rock(SEED, tile + ivec2(half as i32, 0)));
+
+		let left = &level.chunks[&i16vec2(1, 0)].terrain;
+		let right = &level.chunks[&i16vec2(2, 0)].terrain;
+
+		for y in 0..Chunk::SIZE {
+			assert_eq!(straddling.terrain[y][..half], left[y][half..], "row {}", y);
+			assert_eq!(straddling.terrain[y][half..], right[y][..half], "row {}", y);
 		}
 	}
 
-	pub fn draw(&self, camera: &Camera2D) {
-		for (_, chunk) in self.chunks.iter() {
-			// draw if it is in the viewport
-			let center = (chunk.pos + Self::CHUNK_PIXEL_SIZE).as_vec2();
-			
-			if center.distance(camera.target) <= screen_width() {
-				chunk.draw();
+	#[test]
+	fn spawn_is_always_open() {
+		for seed in 0..20 {
+			let level = Level::new(seed);
+			assert!(!level.is_wall_at(Vec2::ZERO), "seed {} spawns in rock", seed);
+		}
+	}
+
+	#[test]
+	fn walls_match_terrain() {
+		let level = Level::new(SEED);
+		let chunk = &level.chunks[&I16Vec2::ZERO];
+
+		let rock = chunk.terrain.iter().flatten().filter(|&&t| t).count();
+		assert_eq!(chunk.colliders.len(), rock);
+
+		for wall in &chunk.colliders {
+			let (x, y) = chunk.local_tile(wall.rect.point());
+			assert!(chunk.terrain[y][x]);
+		}
+
+		for item in &chunk.items {
+			let (x, y) = chunk.local_tile(item.pos);
+			assert!(!chunk.terrain[y][x], "item generated inside rock");
+		}
+	}
+
+	#[test]
+	fn deep_ores_stay_deep() {
+		let level = Level::new(SEED);
+
+		for wall in &level.chunks[&I16Vec2::ZERO].colliders {
+			if let Some(ore) = wall.data.ore() {
+				assert_eq!(ore.min_depth(), 0, "{:?} generated in the starting chunk", ore);
 			}
 		}
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn mining_breaks_walls_and_drops_stone() {
+		let mut level = Level::new(SEED);
+		clear(&mut level, 2, 2, 3, 3);
+		put_wall(&mut level, 3, 3);
+
+		level.damage_terrain(tile(3, 3), 1.);
+		assert!(level.is_wall_at(tile(3, 3)));
+		assert!(level.take_items_at(tile(3, 3)).is_empty());
+
+		level.damage_terrain(tile(3, 3), 2.);
+		assert!(!level.is_wall_at(tile(3, 3)));
+		assert_eq!(level.take_items_at(tile(3, 3)), vec![ItemStack::new(ItemType::Stone, 1)]);
+
+		let chunk = level.get_chunk(tile(3, 3)).unwrap();
+		let (x, y) = chunk.local_tile(tile(3, 3));
+		assert!(!chunk.terrain[y][x]);
+	}
+
+	#[test]
+	fn ore_walls_drop_their_ore() {
+		let mut level = Level::new(SEED);
+		clear(&mut level, 2, 2, 3, 3);
+
+		let chunk = level.get_chunk_mut(tile(3, 3)).unwrap();
+		chunk.colliders.push(Wall::new(tile(3, 3), OreType::Gold.wall_data(2)));
+
+		level.damage_terrain(tile(3, 3), f32::MAX);
+		assert_eq!(level.take_items_at(tile(3, 3)), vec![ItemStack::new(ItemType::GoldOre, 2)]);
+	}
+
+	#[test]
+	fn blueprints_need_room_and_materials() {
+		let mut level = Level::new(SEED);
+		clear(&mut level, 0, 0, 8, 6);
+
+		let mut inventory = Inventory::new(100.);
+		let mut blueprint = Blueprint::new(tile(0, 1), BlueprintType::SteelWall);
+
+		blueprint.update_valid(&level, &inventory);
+		assert!(!blueprint.valid);
+		assert!(blueprint.invalid_reason.as_ref().unwrap().starts_with("Needs"));
+
+		inventory.add(ItemStack::new(ItemType::SteelPlate, 4));
+		blueprint.update_valid(&level, &inventory);
+		assert!(blueprint.valid);
+
+		put_wall(&mut level, 1, 1);
+		blueprint.update_valid(&level, &inventory);
+		assert!(!blueprint.valid);
+		assert_eq!(blueprint.invalid_reason.as_deref(), Some("Blocked by rock"));
+
+		clear(&mut level, 1, 1, 1, 1);
+		blueprint.update_valid(&level, &inventory);
+		blueprint.place(&mut level, &mut inventory);
+
+		assert_eq!(inventory.count(ItemType::SteelPlate), 2);
+		assert!(level.structure_at(tile(1, 1) + T_SIZE / 2.).is_some());
+		assert!(!blueprint.valid);
+		assert_eq!(blueprint.invalid_reason.as_deref(), Some("Blocked by a structure"));
+
+		// Neighbouring structures share an edge without overlapping
+		blueprint.move_toward(vec2(T_SIZE, 0.), &level, &inventory);
+		assert!(blueprint.valid);
+	}
+
+	#[test]
+	fn deconstructing_refunds_part_of_the_cost() {
+		let mut level = Level::new(SEED);
+		clear(&mut level, 0, 0, 8, 6);
+
+		let mut inventory = Inventory::new(100.);
+		inventory.add(ItemStack::new(ItemType::SteelPlate, 2));
+
+		let mut blueprint = Blueprint::new(tile(0, 1), BlueprintType::SteelWall);
+		blueprint.update_valid(&level, &inventory);
+		blueprint.place(&mut level, &mut inventory);
+
+		assert!(level.deconstruct(tile(1, 1) + T_SIZE / 2.));
+		assert!(level.structure_at(tile(1, 1) + T_SIZE / 2.).is_none());
+
+		let refund = level.take_items_at(tile(1, 1));
+		assert_eq!(refund, BlueprintType::SteelWall.refund(Level::DECONSTRUCT_REFUND));
+	}
+
+	#[test]
+	fn destroyed_structures_leave_rubble_and_spill_their_slots() {
+		let mut level = Level::new(SEED);
+		clear(&mut level, 0, 0, 8, 6);
+		level.get_chunk_mut(tile(0, 0)).unwrap().decals.clear();
+
+		let rect = footprint(1, 1, Crusher::SIZE);
+		let mut crusher = Box::new(Crusher::new(rect, Orientation::North)) as Box<dyn Structure>;
+		crusher.slots_mut()[0].insert(ItemStack::new(ItemType::IronOre, 4));
+		crusher.hurt(Crusher::MAX_HP);
+		level.add_structure(crusher);
+
+		level.tick();
+		assert!(level.structure_at(rect.center()).is_none());
+
+		let rubble = level.get_chunk(tile(0, 0)).unwrap().decals.iter()
+			.map(|d| (d.pos, d.tex.as_str()))
+			.collect::<Vec<(Vec2, &str)>>();
+		let footprint = (1..6)
+			.flat_map(|x| (1..4).map(move |y| (tile(x, y), "stone_rubble")))
+			.collect::<Vec<(Vec2, &str)>>();
+		assert_eq!(rubble, footprint);
+
+		let dropped = level.take_items_at(snap_to_tile(rect.center()));
+		let count = |item| dropped.iter().filter(|s| s.item == item).map(|s| s.amount).sum::<i32>();
+		let refund = BlueprintType::Crusher.refund(Level::DESTROYED_REFUND);
+
+		assert!(!refund.is_empty());
+		for stack in refund {
+			let spilled = if stack.item == ItemType::IronOre { 4 } else { 0 };
+			assert_eq!(count(stack.item), stack.amount + spilled);
+		}
+	}
+
+	fn place_belt(level: &mut Level, x: i32, y: i32, orientation: Orientation) {
+		level.add_structure(Box::new(Conveyor::new(footprint(x, y, Conveyor::SIZE), orientation)));
+	}
+
+	fn on_belt(level: &Level, x: i32, y: i32) -> Option<ItemStack> {
+		level.structure_at(tile(x, y) + T_SIZE / 2.).unwrap().slots()[0].stack
+	}
+
+	#[test]
+	fn belts_carry_floor_items_into_a_facing_input() {
+		let mut level = Level::new(SEED);
+		clear(&mut level, 0, 0, 12, 5);
+
+		let stone = ItemStack::new(ItemType::Stone, 3);
+		let crusher = footprint(5, 0, Crusher::SIZE);
+		level.add_structure(Box::new(Crusher::new(crusher, Orientation::North)));
+
+		// Running east into the crusher's input on its west side
+		for x in 1..5 {
+			place_belt(&mut level, x, 1, Orientation::East);
+		}
+
+		level.drop_item(tile(1, 1), stone);
+
+		// Picked up and passed on in the same turn, then one tile a turn after that
+		for x in 2..5 {
+			level.tick();
+			assert_eq!(on_belt(&level, x, 1), Some(stone));
+			assert!((1..5).filter(|&b| b != x).all(|b| on_belt(&level, b, 1).is_none()));
+		}
+
+		level.tick();
+		assert_eq!(on_belt(&level, 4, 1), None);
+		assert_eq!(level.structure_at(crusher.center()).unwrap().slots()[0].stack, Some(stone));
+		assert!(level.take_items_at(tile(1, 1)).is_empty());
+	}
+
+	#[test]
+	fn machines_unload_onto_belts_that_drop_at_the_end() {
+		let mut level = Level::new(SEED);
+		clear(&mut level, 0, 0, 12, 5);
+
+		let gravel = ItemStack::new(ItemType::Gravel, 2);
+		let crusher = footprint(0, 0, Crusher::SIZE);
+		let mut machine = Box::new(Crusher::new(crusher, Orientation::North)) as Box<dyn Structure>;
+		machine.slots_mut().iter_mut().find(|s| s.kind == SlotKind::Output).unwrap().insert(gravel);
+		level.add_structure(machine);
+
+		// The output is on the east side
+		place_belt(&mut level, 5, 1, Orientation::East);
+		place_belt(&mut level, 6, 1, Orientation::East);
+
+		level.tick();
+		assert_eq!(on_belt(&level, 5, 1), Some(gravel));
+		assert!(level.structure_at(crusher.center()).unwrap().slots().iter().all(|s| s.stack.is_none()));
+
+		level.tick();
+		assert_eq!(on_belt(&level, 6, 1), Some(gravel));
+
+		level.tick();
+		assert_eq!(on_belt(&level, 6, 1), None);
+		assert_eq!(level.take_items_at(tile(7, 1)), vec![gravel]);
+	}
+
+	#[test]
+	fn rock_blocks_line_of_sight() {
+		let mut level = Level::new(SEED);
+		clear(&mut level, 0, 0, 10, 10);
+
+		let from = tile(1, 5) + T_SIZE / 2.;
+		assert!(level.line_of_sight(from, tile(8, 5) + T_SIZE / 2.));
+		assert!(level.line_of_sight(from, tile(7, 1) + T_SIZE / 2.));
+
+		put_wall(&mut level, 4, 5);
+		assert!(!level.line_of_sight(from, tile(8, 5) + T_SIZE / 2.));
+		assert!(level.line_of_sight(from, tile(8, 1) + T_SIZE / 2.));
+
+		// Walls themselves can be seen
+		assert!(level.line_of_sight(from, tile(4, 5) + T_SIZE / 2.));
+
+		// Nor through a diagonal gap too tight to walk through
+		put_wall(&mut level, 2, 1);
+		put_wall(&mut level, 1, 2);
+		assert!(!level.line_of_sight(tile(1, 1) + T_SIZE / 2., tile(3, 3) + T_SIZE / 2.));
+		assert!(level.line_of_sight(tile(2, 2) + T_SIZE / 2., tile(3, 3) + T_SIZE / 2.));
+	}
+
+	#[test]
+	fn fog_is_only_rebaked_when_the_view_changes() {
+		let mut level = Level::new(SEED);
+		clear(&mut level, 0, 0, 10, 10);
+
+		let stale = |level: &Level| level.chunks[&I16Vec2::ZERO].fog_stale;
+		let settle = |level: &mut Level| level.chunks.get_mut(&I16Vec2::ZERO).unwrap().fog_stale = false;
+
+		assert!(stale(&level));
+
+		level.update_fov(tile(1, 5), 8);
+		assert!(stale(&level));
+		settle(&mut level);
+
+		level.update_fov(tile(1, 5), 8);
+		assert!(!stale(&level));
+
+		level.update_fov(tile(2, 5), 8);
+		assert!(stale(&level));
+		settle(&mut level);
+
+		// A wall going up cuts the view short even without moving
+		put_wall(&mut level, 4, 5);
+		level.update_fov(tile(2, 5), 8);
+		assert!(stale(&level));
+	}
+
+	#[test]
+	fn seen_tiles_are_remembered_out_of_view() {
+		let mut level = Level::new(SEED);
+		clear(&mut level, 0, 0, 10, 10);
+		put_wall(&mut level, 4, 5);
+
+		level.update_fov(tile(1, 5), 8);
+		assert!(level.is_visible(tile(3, 5)));
+		assert!(level.is_visible(tile(4, 5)));
+		assert!(!level.is_visible(tile(5, 5)));
+		assert!(!level.is_seen(tile(5, 5)));
+
+		level.update_fov(tile(5, 1), 4);
+		assert!(level.is_visible(tile(5, 5)));
+		assert!(!level.is_visible(tile(0, 5)));
+		assert!(level.is_seen(tile(0, 5)));
+	}
+}
@@ -0,0 +1,412 @@
+use macroquad::prelude::*;
+
+use hot_assets::*;
+
+use crate::primitives::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ItemType {
+	Stone,
+	IronOre,
+	GoldOre,
+	TesiumOre,
+	DeotiumOre,
+	EdyagiteOre,
+	LashaliteOre,
+	CrushedIronOre,
+	CrushedGoldOre,
+	CrushedTesiumOre,
+	CrushedDeotiumOre,
+	CrushedEdyagiteOre,
+	CrushedLashaliteOre,
+	Gravel,
+	LatalaShroom,
+	ShroomPulp,
+	Diesel,
+	IronIngot,
+	GoldIngot,
+	TesiumIngot,
+	DeotiumIngot,
+	EdyagiteIngot,
+	LashaliteIngot,
+	SteelPlate,
+}
+
+impl ItemType {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Stone => "Stone",
+			Self::IronOre => "Iron Ore",
+			Self::GoldOre => "Gold Ore",
+			Self::TesiumOre => "Tesium Ore",
+			Self::DeotiumOre => "Deotium Ore",
+			Self::EdyagiteOre => "Edyagite Ore",
+			Self::LashaliteOre => "Lashalite Ore",
+			Self::CrushedIronOre => "Crushed Iron Ore",
+			Self::CrushedGoldOre => "Crushed Gold Ore",
+			Self::CrushedTesiumOre => "Crushed Tesium Ore",
+			Self::CrushedDeotiumOre => "Crushed Deotium Ore",
+			Self::CrushedEdyagiteOre => "Crushed Edyagite Ore",
+			Self::CrushedLashaliteOre => "Crushed Lashalite Ore",
+			Self::Gravel => "Gravel",
+			Self::LatalaShroom => "Latala Shroom",
+			Self::ShroomPulp => "Shroom Pulp",
+			Self::Diesel => "Diesel",
+			Self::IronIngot => "Iron Ingot",
+			Self::GoldIngot => "Gold Ingot",
+			Self::TesiumIngot => "Tesium Ingot",
+			Self::DeotiumIngot => "Deotium Ingot",
+			Self::EdyagiteIngot => "Edyagite Ingot",
+			Self::LashaliteIngot => "Lashalite Ingot",
+			Self::SteelPlate => "Steel Plate",
+		}
+	}
+
+	// What a crusher turns this into, and the byproduct it sometimes throws off
+	pub fn crushed(&self) -> Option<(ItemType, Option<ItemType>)> {
+		match self {
+			Self::Stone => Some((Self::Gravel, None)),
+			Self::IronOre => Some((Self::CrushedIronOre, Some(Self::Gravel))),
+			Self::GoldOre => Some((Self::CrushedGoldOre, Some(Self::Gravel))),
+			Self::TesiumOre => Some((Self::CrushedTesiumOre, Some(Self::Gravel))),
+			Self::DeotiumOre => Some((Self::CrushedDeotiumOre, Some(Self::Gravel))),
+			Self::EdyagiteOre => Some((Self::CrushedEdyagiteOre, Some(Self::Gravel))),
+			Self::LashaliteOre => Some((Self::CrushedLashaliteOre, Some(Self::Gravel))),
+			Self::LatalaShroom => Some((Self::ShroomPulp, None)),
+			_ => None,
+		}
+	}
+
+	// Turns of full load a generator gets out of burning one of these
+	pub fn fuel_value(&self) -> Option<f32> {
+		match self {
+			Self::LatalaShroom => Some(5.),
+			Self::Diesel => Some(40.),
+			_ => None,
+		}
+	}
+
+	// Items without a sprite are drawn as a coloured square
+	pub fn texture(&self, amount: i32) -> Option<String> {
+		match self {
+			Self::LatalaShroom => Some(format!("latala_shroom_{}", (amount.clamp(1, 3) - 1))),
+			_ => None,
+		}
+	}
+
+	pub fn weight(&self) -> f32 {
+		match self {
+			Self::Stone => 2.,
+			Self::IronOre => 3.,
+			Self::GoldOre => 4.,
+			Self::TesiumOre => 2.,
+			Self::DeotiumOre => 3.,
+			Self::EdyagiteOre => 3.,
+			Self::LashaliteOre => 1.,
+			Self::CrushedIronOre => 3.,
+			Self::CrushedGoldOre => 4.,
+			Self::CrushedTesiumOre => 2.,
+			Self::CrushedDeotiumOre => 3.,
+			Self::CrushedEdyagiteOre => 3.,
+			Self::CrushedLashaliteOre => 1.,
+			Self::Gravel => 1.,
+			Self::LatalaShroom => 0.5,
+			Self::ShroomPulp => 0.5,
+			Self::Diesel => 2.,
+			Self::IronIngot => 2.,
+			Self::GoldIngot => 3.,
+			Self::TesiumIngot => 1.,
+			Self::DeotiumIngot => 2.,
+			Self::EdyagiteIngot => 2.,
+			Self::LashaliteIngot => 1.,
+			Self::SteelPlate => 3.,
+		}
+	}
+
+	pub fn max_stack(&self) -> i32 {
+		match self {
+			Self::Stone | Self::Gravel => 50,
+			_ => 25,
+		}
+	}
+
+	pub fn color(&self) -> Color {
+		match self {
+			Self::Stone => GRAY,
+			Self::IronOre => Color::from_rgba(160, 90, 60, 255),
+			Self::GoldOre => GOLD,
+			Self::TesiumOre => SKYBLUE,
+			Self::DeotiumOre => PURPLE,
+			Self::EdyagiteOre => LIME,
+			Self::LashaliteOre => PINK,
+			Self::CrushedIronOre => Color::from_rgba(190, 120, 90, 255),
+			Self::CrushedGoldOre => YELLOW,
+			Self::CrushedTesiumOre => BLUE,
+			Self::CrushedDeotiumOre => VIOLET,
+			Self::CrushedEdyagiteOre => GREEN,
+			Self::CrushedLashaliteOre => MAGENTA,
+			Self::Gravel => LIGHTGRAY,
+			Self::LatalaShroom => Color::from_rgba(120, 220, 180, 255),
+			Self::ShroomPulp => Color::from_rgba(90, 170, 140, 255),
+			Self::Diesel => Color::from_rgba(60, 40, 20, 255),
+			Self::IronIngot => Color::from_rgba(200, 200, 210, 255),
+			Self::GoldIngot => ORANGE,
+			Self::TesiumIngot => Color::from_rgba(120, 200, 255, 255),
+			Self::DeotiumIngot => DARKPURPLE,
+			Self::EdyagiteIngot => DARKGREEN,
+			Self::LashaliteIngot => Color::from_rgba(255, 150, 200, 255),
+			Self::SteelPlate => DARKGRAY,
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ItemStack {
+	pub item: ItemType,
+	pub amount: i32,
+}
+
+impl ItemStack {
+	pub fn new(item: ItemType, amount: i32) -> Self {
+		Self {
+			item, amount
+		}
+	}
+}
+
+pub struct FloorItem {
+	pub pos: Vec2,
+	pub stack: ItemStack,
+}
+
+impl FloorItem {
+	pub fn new(pos: Vec2, stack: ItemStack) -> Self {
+		Self {
+			pos, stack
+		}
+	}
+
+	pub fn draw(&self, assets: &AssetManager) {
+		if let Some(tex) = self.stack.item.texture(self.stack.amount) {
+			draw_texture(&assets.images[&tex], self.pos.x, self.pos.y, WHITE);
+			return;
+		}
+
+		let size = T_SIZE / 2.;
+		let x = self.pos.x + size / 2.;
+		let y = self.pos.y + size / 2.;
+
+		draw_rectangle(x, y, size, size, self.stack.item.color());
+		draw_rectangle_lines(x, y, size, size, 2., BLACK);
+		draw_text(&format!("{}", self.stack.amount), self.pos.x + 2., self.pos.y + T_SIZE - 2., T_SIZE / 3., WHITE);
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SlotKind {
+	Input,
+	Output,
+	// Takes items in and passes them on again, like on a conveyor
+	Belt,
+}
+
+// A single item slot on a machine. Players can only put things into input and belt slots,
+// but the machine itself fills its outputs through `insert` like anything else.
+pub struct Slot {
+	pub name: &'static str,
+	pub kind: SlotKind,
+	pub stack: Option<ItemStack>,
+	filter: fn(ItemType) -> bool,
+}
+
+impl Slot {
+	pub fn new(name: &'static str, kind: SlotKind, filter: fn(ItemType) -> bool) -> Self {
+		Self {
+			name,
+			kind,
+			stack: None,
+			filter,
+		}
+	}
+
+	pub fn output(name: &'static str) -> Self {
+		Self::new(name, SlotKind::Output, |_| true)
+	}
+
+	pub fn accepts(&self, item: ItemType) -> bool {
+		if let Some(stack) = self.stack {
+			if stack.item != item {
+				return false;
+			}
+		}
+
+		(self.filter)(item)
+	}
+
+	pub fn space_for(&self, item: ItemType) -> i32 {
+		if !self.accepts(item) {
+			return 0;
+		}
+
+		item.max_stack() - self.stack.map(|s| s.amount).unwrap_or(0)
+	}
+
+	// Hands back whatever didn't fit
+	pub fn insert(&mut self, stack: ItemStack) -> Option<ItemStack> {
+		let moved = stack.amount.min(self.space_for(stack.item));
+
+		if moved > 0 {
+			let held = self.stack.get_or_insert(ItemStack::new(stack.item, 0));
+			held.amount += moved;
+		}
+
+		if moved < stack.amount {
+			Some(ItemStack::new(stack.item, stack.amount - moved))
+		} else {
+			None
+		}
+	}
+
+	pub fn take(&mut self, amount: i32) -> Option<ItemStack> {
+		let held = self.stack.as_mut()?;
+		let moved = amount.min(held.amount);
+		held.amount -= moved;
+
+		let taken = ItemStack::new(held.item, moved);
+
+		if held.amount <= 0 {
+			self.stack = None;
+		}
+
+		Some(taken)
+	}
+}
+
+pub struct Inventory {
+	pub stacks: Vec<ItemStack>,
+	pub capacity: f32,
+}
+
+impl Inventory {
+	pub fn new(capacity: f32) -> Self {
+		Self {
+			stacks: Vec::new(),
+			capacity,
+		}
+	}
+
+	pub fn weight(&self) -> f32 {
+		self.stacks.iter().map(|s| s.item.weight() * s.amount as f32).sum()
+	}
+
+	pub fn count(&self, item: ItemType) -> i32 {
+		self.stacks.iter().filter(|s| s.item == item).map(|s| s.amount).sum()
+	}
+
+	pub fn has(&self, item: ItemType, amount: i32) -> bool {
+		self.count(item) >= amount
+	}
+
+	// Puts in as much of the stack as the weight limit allows, topping up existing stacks
+	// before starting new ones. Whatever doesn't fit is handed back.
+	pub fn add(&mut self, stack: ItemStack) -> Option<ItemStack> {
+		let free_weight = self.capacity - self.weight();
+		let fits = if stack.item.weight() > 0. {
+			((free_weight / stack.item.weight()).floor() as i32).clamp(0, stack.amount)
+		} else {
+			stack.amount
+		};
+
+		let mut left = fits;
+
+		for held in self.stacks.iter_mut().filter(|s| s.item == stack.item) {
+			let moved = left.min(stack.item.max_stack() - held.amount).max(0);
+			held.amount += moved;
+			left -= moved;
+		}
+
+		while left > 0 {
+			let moved = left.min(stack.item.max_stack());
+			self.stacks.push(ItemStack::new(stack.item, moved));
+			left -= moved;
+		}
+
+		if fits < stack.amount {
+			Some(ItemStack::new(stack.item, stack.amount - fits))
+		} else {
+			None
+		}
+	}
+
+	// Takes up to `amount` of an item out, newest stacks first
+	pub fn take(&mut self, item: ItemType, amount: i32) -> Option<ItemStack> {
+		let mut left = amount;
+
+		for i in (0..self.stacks.len()).rev() {
+			if left == 0 {
+				break;
+			}
+
+			if self.stacks[i].item == item {
+				let moved = left.min(self.stacks[i].amount);
+				self.stacks[i].amount -= moved;
+				left -= moved;
+			}
+		}
+
+		self.stacks.retain(|s| s.amount > 0);
+
+		if left == amount {
+			None
+		} else {
+			Some(ItemStack::new(item, amount - left))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn amounts(inventory: &Inventory) -> Vec<i32> {
+		inventory.stacks.iter().map(|s| s.amount).collect()
+	}
+
+	#[test]
+	fn adding_stops_at_the_weight_limit() {
+		let mut inventory = Inventory::new(100.);
+
+		let rest = inventory.add(ItemStack::new(ItemType::Stone, 60));
+		assert_eq!(rest, Some(ItemStack::new(ItemType::Stone, 10)));
+		assert_eq!(inventory.weight(), 100.);
+
+		assert_eq!(inventory.add(ItemStack::new(ItemType::IronOre, 1)), Some(ItemStack::new(ItemType::IronOre, 1)));
+		assert_eq!(inventory.count(ItemType::IronOre), 0);
+	}
+
+	#[test]
+	fn stacks_fill_up_before_new_ones_start() {
+		let mut inventory = Inventory::new(1000.);
+
+		assert_eq!(inventory.add(ItemStack::new(ItemType::Stone, 30)), None);
+		assert_eq!(inventory.add(ItemStack::new(ItemType::Stone, 30)), None);
+		assert_eq!(amounts(&inventory), vec![ItemType::Stone.max_stack(), 10]);
+
+		inventory.add(ItemStack::new(ItemType::IronOre, 60));
+		assert_eq!(inventory.count(ItemType::IronOre), 60);
+		assert!(inventory.stacks.iter().all(|s| s.amount <= s.item.max_stack()));
+	}
+
+	#[test]
+	fn taking_empties_the_newest_stacks_first() {
+		let mut inventory = Inventory::new(1000.);
+		inventory.add(ItemStack::new(ItemType::Stone, 60));
+
+		assert_eq!(inventory.take(ItemType::Stone, 15), Some(ItemStack::new(ItemType::Stone, 15)));
+		assert_eq!(amounts(&inventory), vec![45]);
+
+		assert_eq!(inventory.take(ItemType::Stone, 100), Some(ItemStack::new(ItemType::Stone, 45)));
+		assert!(inventory.stacks.is_empty());
+		assert_eq!(inventory.take(ItemType::Stone, 1), None);
+	}
+}
@@ -1,119 +1,740 @@
+use ::rand::Rng;
 use macroquad::prelude::*;
 
 use hot_assets::*;
 
 use crate::primitives::*;
-use crate::level::*;
+use crate::generation::*;
 use crate::a_star::*;
 use crate::types::*;
+use crate::scheduler::*;
+use crate::entities::*;
+use crate::combat::*;
+use crate::items::*;
+use crate::level::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum KoboldState {
+	// Nothing in sight, so ambling about and looking for mischief
+	Wander,
+	// After `target`, heading for where it was last seen. `memory` counts down the turns
+	// since then, and the hunt is given up once it runs out.
+	Hunt {
+		target: usize,
+		last_seen: Vec2,
+		memory: i32,
+	},
+	// Too hurt to fight, so getting away from where the threat was
+	Flee {
+		from: Vec2,
+	},
+	// Off to pinch whatever is lying on the tile at `goal`
+	Steal {
+		goal: Vec2,
+	},
+	// Off to wreck the structure at `goal`
+	Sabotage {
+		goal: Vec2,
+	},
+}
+
+// How a path may get to its goal
+#[derive(Copy, Clone, PartialEq)]
+enum Route {
+	// Only over open ground
+	Walk,
+	// Up alongside whatever is in the rect, which can be solid itself
+	UpTo(Rect),
+	// Over open ground if it can, otherwise digging and smashing through whatever is cheapest
+	Breach,
+}
 
 pub struct Kobold {
 	pub id: usize,
 	rect: Rect,
-	target: Option<*mut Box<dyn Entity>>,
+	pub state: KoboldState,
 	path: Option<Vec<Vec2>>,
+	// Where the path was planned to
+	path_goal: Vec2,
+	// The last few goals no path could be found to, so they aren't planned for again every turn
+	unreachable: Vec<Vec2>,
+	energy: Energy,
+
+	// Whatever it has made off with
+	pub inventory: Inventory,
 
-	tex: *const Texture2D,
+	tex: &'static str,
 
 	hp: i32,
 	dead: bool,
 }
 
 impl Kobold {
-	pub const MAX_HP: i32 = 10;
+	pub const MAX_HP: i32 = 8;
+	pub const STATS: Stats = Stats {
+		accuracy: 0.6,
+		min_damage: 1,
+		max_damage: 3,
+		armor: 0,
+	};
+	// A bit quicker than a dwarf
+	pub const SPEED: i32 = NORMAL_SPEED * 6 / 5;
+	pub const CARRY_CAPACITY: f32 = 20.;
 
-	pub fn new(pos: Vec2, assets: &AssetManager) -> Self {
+	// Tiles a kobold can see across, given a clear line
+	pub const SIGHT_RANGE: f32 = 8.;
+	// Turns a hunt goes on after losing sight of the target
+	pub const MEMORY: i32 = 10;
+	// Turns tail at or below this
+	pub const FLEE_HP: i32 = 3;
+	// Tiles from the threat where a fleeing kobold feels safe again
+	pub const SAFE_DISTANCE: f32 = 12.;
+	// Chance every idle turn to take a step
+	pub const WANDER_CHANCE: f64 = 0.5;
+	// Chance every idle turn to go looking for something to steal or break
+	pub const MISCHIEF_CHANCE: f64 = 0.2;
+	// Hardness taken off a cave wall with every turn of digging
+	pub const DIG_POWER: f32 = 0.2;
+	// Unreachable goals remembered at once, oldest forgotten first
+	pub const UNREACHABLE_MEMORY: usize = 8;
+
+	pub fn new(pos: Vec2) -> Self {
 		Self {
 			id: 0,
 
 			rect: Rect::new(pos.x, pos.y, T_SIZE, T_SIZE),
-			target: None,
+			state: KoboldState::Wander,
 			path: None,
-			
-			tex: &assets.images["kobold"] as *const Texture2D,
+			path_goal: Vec2::ZERO,
+			unreachable: Vec::new(),
+			energy: Energy::new(Self::SPEED),
+
+			inventory: Inventory::new(Self::CARRY_CAPACITY),
+
+			tex: "kobold",
 
 			hp: Self::MAX_HP,
 			dead: false,
 		}
 	}
 
-	fn move_toward(&mut self, direction: Vec2, world: &mut Chunk) -> bool {
-		let d_pos = self.rect.point() + direction;
-		let check_wall = Wall::new(d_pos, WallData::Basic);
-
-		if world.colliders.iter().position(|w: &Wall| w.rect == check_wall.rect).is_some() {
+	// Steps onto a neighbouring tile, held to the same corner rule as paths
+	fn move_to(&mut self, new_pos: Vec2, world: &mut Level, entities: &Entities) -> bool {
+		if !is_passable(world, new_pos) || !within_reach(world, self.rect.point(), new_pos) || entities.at(new_pos + T_SIZE / 2.).is_some() {
 			return true;
 		}
 
-		self.rect.move_to(d_pos);
+		self.rect.move_to(new_pos);
 
 		false
 	}
 
-	fn move_to(&mut self, new_pos: Vec2, world: &mut Chunk) -> bool {
-		if world.colliders.iter().position(|w: &Wall| w.rect.point() == new_pos).is_some() {
-			return true;
+	fn sees(&self, world: &Level, pos: Vec2) -> bool {
+		self.rect.center().distance(pos) <= Self::SIGHT_RANGE * T_SIZE && world.line_of_sight(self.rect.center(), pos)
+	}
+
+	// The nearest hostile in sight, sticking with the current target while it's still visible
+	fn spot(&self, entities: &Entities, world: &Level) -> Option<(usize, Vec2)> {
+		let visible = |e: &dyn Entity| hostile(self.faction(), e.faction()) && self.sees(world, e.rect().center());
+		let current = match self.state {
+			KoboldState::Hunt { target, .. } => Some(target),
+			_ => None,
+		};
+
+		current.and_then(|id| entities.get(id)).filter(|&e| visible(e))
+			.or_else(|| entities.nearest(self.rect.center(), visible).and_then(|id| entities.get(id)))
+			.map(|e| (e.id(), e.rect().point()))
+	}
+
+	// Something lying about to pinch, or failing that a structure to wreck
+	fn find_mischief(&self, world: &Level) -> Option<KoboldState> {
+		let pos = self.rect.center();
+		let by_distance = |a: &Vec2, b: &Vec2| a.distance_squared(pos).total_cmp(&b.distance_squared(pos));
+
+		let loot = world.items()
+			.map(|i| i.pos)
+			.filter(|&p| self.can_reach(p) && self.sees(world, p + T_SIZE / 2.))
+			.min_by(by_distance);
+
+		if let Some(goal) = loot.filter(|_| self.inventory.weight() < Self::CARRY_CAPACITY) {
+			return Some(KoboldState::Steal { goal });
 		}
 
-		self.rect.move_to(new_pos);
+		world.structures()
+			.filter(|s| s.blueprint_type().value() > 0 && self.can_reach(s.rect().center()))
+			.filter(|s| self.sees(world, s.rect().center()))
+			.max_by(|a, b| {
+				a.blueprint_type().value().cmp(&b.blueprint_type().value())
+					.then_with(|| by_distance(&b.rect().center(), &a.rect().center()))
+			})
+			.map(|s| KoboldState::Sabotage { goal: s.rect().center() })
+	}
 
-		false
+	// As far as it knows. Only goals that failed to plan recently count as out of reach.
+	fn can_reach(&self, goal: Vec2) -> bool {
+		!self.unreachable.contains(&snap_to_tile(goal))
+	}
+
+	// Roughly how many turns it would take to get through a tile, as extra path cost. Cave
+	// walls can be dug and solid structures smashed, but ore is too hard to bother with.
+	fn breach_cost(world: &Level, tile: IVec2) -> Option<i32> {
+		let pos = from_tile(tile);
+
+		if !world.is_loaded(pos) {
+			return None;
+		}
+
+		let turns = if let Some(wall) = world.wall_at(pos) {
+			match wall.data {
+				WallData::CaveWall { hardness } => (hardness / Self::DIG_POWER).ceil() as i32,
+				_ => return None,
+			}
+		} else {
+			match world.structure_at(pos + T_SIZE / 2.) {
+				Some(s) if s.collides() => s.hp() * 2 / (Self::STATS.min_damage + Self::STATS.max_damage),
+				_ => 0,
+			}
+		};
+
+		Some(turns * STRAIGHT_COST)
+	}
+
+	// Picks what to be doing this turn. Anything hostile in sight is hunted, or run from once
+	// badly hurt. Otherwise it keeps at whatever it was doing while that still makes sense.
+	fn think(&self, entities: &Entities, world: &mut Level) -> KoboldState {
+		let pos = self.rect.point();
+		let hurt = self.hp <= Self::FLEE_HP;
+
+		match (self.spot(entities, world), hurt) {
+			(Some((_, threat)), true) => return KoboldState::Flee { from: threat },
+			(Some((target, last_seen)), false) if self.can_reach(last_seen) => {
+				return KoboldState::Hunt { target, last_seen, memory: Self::MEMORY };
+			}
+			_ => {}
+		}
+
+		match self.state {
+			KoboldState::Flee { from } if hurt && from.distance(pos) < Self::SAFE_DISTANCE * T_SIZE => return self.state,
+			KoboldState::Hunt { target, last_seen, memory } if !hurt && memory > 0 && last_seen != pos => {
+				return KoboldState::Hunt { target, last_seen, memory: memory - 1 };
+			}
+			KoboldState::Steal { goal } if world.items().any(|i| i.pos == goal) => return self.state,
+			KoboldState::Sabotage { goal } if world.structure_at(goal).is_some() => return self.state,
+			_ => {}
+		}
+
+		if world.rng.gen_bool(Self::MISCHIEF_CHANCE) {
+			if let Some(state) = self.find_mischief(world) {
+				return state;
+			}
+		}
+
+		KoboldState::Wander
+	}
+
+	fn plan(&self, goal: Vec2, route: Route, world: &Level) -> Option<Vec<Vec2>> {
+		let start = self.rect.point();
+
+		match route {
+			Route::Walk => astar(world, start, goal),
+			Route::UpTo(rect) => astar_by(start, goal, |tile| {
+				let pos = from_tile(tile);
+				let inside = rect.overlaps(&Rect::new(pos.x, pos.y, T_SIZE, T_SIZE));
+				(inside || is_passable(world, pos)).then_some(0)
+			}),
+			Route::Breach => astar(world, start, goal)
+				.or_else(|| astar_by(start, goal, |tile| Self::breach_cost(world, tile))),
+		}
+	}
+
+	// Follows a path to `goal`, planning a new one whenever the goal moves. None when there's
+	// no way there, in which case the goal is remembered as out of reach for a while.
+	fn step_toward(&mut self, goal: Vec2, route: Route, entities: &Entities, world: &mut Level) -> Option<Action> {
+		if !self.can_reach(goal) {
+			return None;
+		}
+
+		if self.path.is_none() || self.path_goal != goal {
+			self.path = self.plan(goal, route, world);
+			self.path_goal = goal;
+
+			if self.path.is_none() {
+				if self.unreachable.len() >= Self::UNREACHABLE_MEMORY {
+					self.unreachable.remove(0);
+				}
+
+				self.unreachable.push(snap_to_tile(goal));
+				return None;
+			}
+		}
+
+		let Some(next) = self.path.as_ref().and_then(|p| p.first().copied()) else {
+			return Some(Action::Wait);
+		};
+
+		if let Route::UpTo(rect) = route {
+			// Already alongside it
+			if rect.overlaps(&Rect::new(next.x, next.y, T_SIZE, T_SIZE)) {
+				return Some(Action::Wait);
+			}
+		}
+
+		if world.is_wall_at(next) {
+			world.damage_terrain(next, Self::DIG_POWER);
+			return Some(Action::Mine);
+		}
+
+		if world.structure_at(next + T_SIZE / 2.).map(|s| s.collides()).unwrap_or(false) {
+			Self::smash(world, next + T_SIZE / 2.);
+			return Some(Action::Attack);
+		}
+
+		if self.move_to(next, world, entities) {
+			// Something moved into the way since planning, so plan again next time
+			self.path = None;
+			return Some(Action::Wait);
+		}
+
+		if let Some(path) = &mut self.path {
+			path.remove(0);
+		}
+
+		Some(Action::Move)
+	}
+
+	// Follows up on `step_toward` coming back empty by going back to wandering
+	fn give_up(&mut self) -> Action {
+		self.state = KoboldState::Wander;
+		self.path = None;
+
+		Action::Wait
+	}
+
+	fn wander(&mut self, entities: &Entities, world: &mut Level) -> Action {
+		if !world.rng.gen_bool(Self::WANDER_CHANCE) {
+			return Action::Wait;
+		}
+
+		let offset = adj_8_t()[world.rng.gen_range(0..8)];
+
+		if self.move_to(self.rect.point() + offset, world, entities) {
+			Action::Wait
+		} else {
+			Action::Move
+		}
+	}
+
+	// Takes whichever neighbouring tile gets furthest from `from`, or cowers when cornered
+	fn flee(&mut self, from: Vec2, entities: &Entities, world: &mut Level) -> Action {
+		let pos = self.rect.point();
+		let further = |p: &Vec2| p.distance_squared(from) > pos.distance_squared(from);
+
+		let best = adj_8_t().into_iter()
+			.map(|offset| pos + offset)
+			.filter(|&p| is_passable(world, p) && within_reach(world, pos, p) && entities.at(p + T_SIZE / 2.).is_none())
+			.max_by(|a, b| a.distance_squared(from).total_cmp(&b.distance_squared(from)))
+			.filter(further);
+
+		let Some(best) = best else {
+			return Action::Wait;
+		};
+
+		self.rect.move_to(best);
+		self.path = None;
+
+		Action::Move
+	}
+
+	fn steal(&mut self, goal: Vec2, entities: &Entities, world: &mut Level) -> Action {
+		if self.rect.point() != goal {
+			return self.step_toward(goal, Route::Walk, entities, world).unwrap_or_else(|| self.give_up());
+		}
+
+		for stack in world.take_items_at(goal) {
+			if let Some(rest) = self.inventory.add(stack) {
+				world.drop_item(goal, rest);
+			}
+		}
+
+		self.state = KoboldState::Wander;
+
+		Action::PickUp
+	}
+
+	// Takes a swing at whatever structure is at `pos`
+	fn smash(world: &mut Level, pos: Vec2) {
+		let damage = world.rng.gen_range(Self::STATS.min_damage..=Self::STATS.max_damage);
+
+		if let Some(structure) = world.structure_at_mut(pos) {
+			structure.hurt(damage);
+		}
+	}
+
+	// Whether any tile under `rect` could be hit from here, going by the melee corner rule
+	fn can_hit(&self, world: &Level, rect: Rect) -> bool {
+		let first = to_tile(rect.point());
+		let last = to_tile(rect.point() + rect.size());
+
+		(first.x..=last.x)
+			.flat_map(|x| (first.y..=last.y).map(move |y| from_tile(ivec2(x, y))))
+			.any(|pos| within_reach(world, self.rect.point(), pos))
+	}
+
+	fn sabotage(&mut self, goal: Vec2, entities: &Entities, world: &mut Level) -> Action {
+		let Some(rect) = world.structure_at(goal).map(|s| s.rect()) else {
+			return self.give_up();
+		};
+
+		if self.can_hit(world, rect) {
+			Self::smash(world, goal);
+			return Action::Attack;
+		}
+
+		self.step_toward(goal, Route::UpTo(rect), entities, world).unwrap_or_else(|| self.give_up())
 	}
 }
 
 lazy_derive!(Damageable, Kobold);
 
 impl Entity for Kobold {
-    fn update(&mut self, entities: *mut Vec<Box<dyn Entity>>, world: &mut Chunk) {
-    	unsafe {
-    		if let Some(target) = &self.target {
-	    		if let Some(path) = &self.path {
-	    			dbg!(path);
-	    		} else {
-	    			match (**target).data() {
-	    				EntityData::Player {rect, ..} => {
-			    			// self.path = Some(astar(world, self.rect.point(), rect.point()));
-			    			if let Some(new_pos) = shite_step(world, self.rect.point(), rect.point()) {
-			    				self.move_to(new_pos, world);
-			    			}
-	    				}
-	    				_ => {}
-	    			}
-	    		}
-	    	} else {
-				for entity in &mut *entities {
-					match entity.data() {
-						EntityData::Player {rect, ..} => {
-							if self.rect.center().distance(rect.center()) <= 3. * T_SIZE {
-								self.target = Some(entity as *mut Box<dyn Entity>);
-								break;
-							}
-						}
-						_ => {}
-					}
-				}
-	    	}
+    fn update(&mut self, entities: &mut Entities, world: &mut Level) -> Action {
+    	self.state = self.think(entities, world);
+
+    	match self.state {
+    		KoboldState::Wander => self.wander(entities, world),
+    		KoboldState::Hunt { target, last_seen, .. } => {
+    			let adjacent = entities.get(target)
+    				.map(|e| within_reach(world, self.rect.point(), e.rect().point()))
+    				.unwrap_or(false);
+
+    			if adjacent {
+    				if let Some(target) = entities.get_mut(target) {
+    					attack(Self::STATS, target, &mut world.rng);
+    					return Action::Attack;
+    				}
+    			}
+
+    			self.step_toward(last_seen, Route::Breach, entities, world).unwrap_or_else(|| self.give_up())
+    		}
+    		KoboldState::Flee { from } => self.flee(from, entities, world),
+    		KoboldState::Steal { goal } => self.steal(goal, entities, world),
+    		KoboldState::Sabotage { goal } => self.sabotage(goal, entities, world),
     	}
     }
 
-    fn draw(&mut self, _entities: *mut Vec<Box<dyn Entity>>, _world: &mut Chunk) {
-    	unsafe {
-    		draw_texture_ex(
-	    		&*self.tex as &Texture2D, self.rect.x, self.rect.y, WHITE,
-	    		DrawTextureParams {
-	    			..Default::default()
-	    		}
-	    	);
-    	}
+    fn drops(&mut self) -> Vec<ItemStack> {
+    	std::mem::take(&mut self.inventory.stacks)
     }
 
-    fn data(&mut self) -> EntityData {
+    fn draw(&self, _world: &Level, assets: &AssetManager) {
+    	draw_texture_ex(
+    		&assets.images[self.tex], self.rect.x, self.rect.y, WHITE,
+    		DrawTextureParams {
+    			..Default::default()
+    		}
+    	);
+
+    	draw_health_bar(self.rect, self.hp, Self::MAX_HP);
+    }
+
+    fn data(&self) -> EntityData {
     	EntityData::Kobold {
     		rect: self.rect,
     	}
     }
 
+    fn rect(&self) -> Rect {
+    	self.rect
+    }
+
+    fn stats(&self) -> Stats {
+    	Self::STATS
+    }
+
+    fn faction(&self) -> Faction {
+    	Faction::Kobolds
+    }
+
+    fn corpse(&self) -> Option<&'static str> {
+    	Some(self.tex)
+    }
+
+    fn energy(&self) -> &Energy {
+    	&self.energy
+    }
+
+    fn energy_mut(&mut self) -> &mut Energy {
+    	&mut self.energy
+    }
+
     fn id(&self) -> usize {
     	self.id
     }
-}
\ No newline at end of file
+
+    fn set_id(&mut self, id: usize) {
+    	self.id = id;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::player::*;
+	use crate::structures::*;
+	use crate::test_utils::*;
+
+	// A level with the top left of the starting chunk mined out, and a dwarf standing in it
+	fn arena(player_at: Vec2) -> (Level, Entities) {
+		let mut level = Level::new(1234);
+		let mut entities = Entities::new();
+
+		clear(&mut level, 0, 0, 16, 10);
+		entities.spawn(Box::new(Player::new(player_at)));
+		entities.flush();
+
+		(level, entities)
+	}
+
+	#[test]
+	fn hunts_what_it_can_see() {
+		let (mut level, mut entities) = arena(tile(6, 2));
+		let mut kobold = Kobold::new(tile(1, 2));
+
+		assert_eq!(kobold.update(&mut entities, &mut level), Action::Move);
+		assert!(matches!(kobold.state, KoboldState::Hunt { last_seen, .. } if last_seen == tile(6, 2)));
+		assert_eq!(kobold.rect.point(), tile(2, 2));
+	}
+
+	#[test]
+	fn no_attacking_round_corners() {
+		let (mut level, mut entities) = arena(tile(3, 3));
+		let mut kobold = Kobold::new(tile(2, 2));
+
+		put_wall(&mut level, 3, 2);
+		put_wall(&mut level, 2, 3);
+
+		assert_ne!(kobold.update(&mut entities, &mut level), Action::Attack);
+		assert_eq!(entities.get(entities.player_id().unwrap()).unwrap().hp(), Player::MAX_HP);
+	}
+
+	#[test]
+	fn walls_hide_the_player() {
+		let (mut level, mut entities) = arena(tile(6, 2));
+		let mut kobold = Kobold::new(tile(1, 2));
+
+		for y in 0..10 {
+			put_wall(&mut level, 4, y);
+		}
+
+		for _ in 0..20 {
+			kobold.update(&mut entities, &mut level);
+			assert!(!matches!(kobold.state, KoboldState::Hunt {..}));
+		}
+	}
+
+	#[test]
+	fn hunts_are_given_up_after_a_while() {
+		let (mut level, mut entities) = arena(tile(14, 2));
+		let mut kobold = Kobold::new(tile(1, 2));
+		let player = entities.player_id().unwrap();
+
+		kobold.state = KoboldState::Hunt {
+			target: player,
+			last_seen: tile(14, 8),
+			memory: 2,
+		};
+
+		// Out of sight range, so it only goes on memory
+		for _ in 0..2 {
+			kobold.update(&mut entities, &mut level);
+			assert!(matches!(kobold.state, KoboldState::Hunt {..}));
+		}
+
+		kobold.update(&mut entities, &mut level);
+		assert!(!matches!(kobold.state, KoboldState::Hunt {..}));
+	}
+
+	#[test]
+	fn badly_hurt_kobolds_run_away() {
+		let (mut level, mut entities) = arena(tile(3, 2));
+		let mut kobold = Kobold::new(tile(5, 2));
+		kobold.hurt(Kobold::MAX_HP - Kobold::FLEE_HP);
+
+		assert_eq!(kobold.update(&mut entities, &mut level), Action::Move);
+		assert_eq!(kobold.state, KoboldState::Flee { from: tile(3, 2) });
+		assert_eq!(kobold.rect.point().x, tile(6, 2).x);
+	}
+
+	#[test]
+	fn no_fleeing_through_diagonal_gaps() {
+		let (mut level, mut entities) = arena(tile(3, 2));
+		let mut kobold = Kobold::new(tile(5, 2));
+		kobold.hurt(Kobold::MAX_HP - Kobold::FLEE_HP);
+
+		// The only way further away is diagonally between (6, 2) and (5, 3)
+		for (x, y) in [(5, 1), (6, 1), (6, 2), (5, 3)] {
+			put_wall(&mut level, x, y);
+		}
+
+		assert_eq!(kobold.update(&mut entities, &mut level), Action::Wait);
+		assert_eq!(kobold.state, KoboldState::Flee { from: tile(3, 2) });
+		assert_eq!(kobold.rect.point(), tile(5, 2));
+	}
+
+	#[test]
+	fn thieves_carry_off_loot_and_drop_it_when_killed() {
+		let (mut level, mut entities) = arena(tile(14, 8));
+		let mut kobold = Kobold::new(tile(1, 2));
+		entities.despawn(entities.player_id().unwrap());
+		entities.flush();
+
+		level.drop_item(tile(3, 2), ItemStack::new(ItemType::Stone, 2));
+		kobold.state = KoboldState::Steal { goal: tile(3, 2) };
+
+		for _ in 0..3 {
+			kobold.update(&mut entities, &mut level);
+		}
+
+		assert_eq!(kobold.inventory.count(ItemType::Stone), 2);
+		assert!(level.take_items_at(tile(3, 2)).is_empty());
+		assert_eq!(kobold.drops(), vec![ItemStack::new(ItemType::Stone, 2)]);
+	}
+
+	#[test]
+	fn saboteurs_wreck_structures() {
+		let (mut level, mut entities) = arena(tile(14, 8));
+		let mut kobold = Kobold::new(tile(1, 2));
+		entities.despawn(entities.player_id().unwrap());
+		entities.flush();
+
+		let rect = place_steel_wall(&mut level, 5, 2);
+		kobold.state = KoboldState::Sabotage { goal: rect.center() };
+
+		for _ in 0..3 {
+			assert_eq!(kobold.update(&mut entities, &mut level), Action::Move);
+		}
+
+		assert_eq!(kobold.update(&mut entities, &mut level), Action::Attack);
+		assert!(level.structure_at(rect.center()).unwrap().hp() < SteelWall::MAX_HP);
+	}
+
+	#[test]
+	fn no_smashing_round_corners() {
+		let (mut level, mut entities) = arena(tile(14, 8));
+		let mut kobold = Kobold::new(tile(4, 1));
+		entities.despawn(entities.player_id().unwrap());
+		entities.flush();
+
+		let rect = place_steel_wall(&mut level, 5, 2);
+		put_wall(&mut level, 5, 1);
+		put_wall(&mut level, 4, 2);
+		kobold.state = KoboldState::Sabotage { goal: rect.center() };
+
+		assert_ne!(kobold.update(&mut entities, &mut level), Action::Attack);
+		assert_eq!(level.structure_at(rect.center()).unwrap().hp(), SteelWall::MAX_HP);
+	}
+
+	#[test]
+	fn hunters_dig_through_rock_to_reach_their_prey() {
+		let (mut level, mut entities) = arena(tile(6, 2));
+		let mut kobold = Kobold::new(tile(1, 2));
+		let player = entities.player_id().unwrap();
+
+		for (x, y) in get_adj(6, 2) {
+			put_wall(&mut level, x, y);
+		}
+
+		kobold.state = KoboldState::Hunt {
+			target: player,
+			last_seen: tile(6, 2),
+			memory: 100,
+		};
+
+		for _ in 0..80 {
+			kobold.update(&mut entities, &mut level);
+		}
+
+		assert!(get_adj(6, 2).into_iter().any(|(x, y)| !level.is_wall_at(tile(x, y))));
+		assert!(entities.get(player).unwrap().hp() < Player::MAX_HP);
+	}
+
+	#[test]
+	fn hunters_smash_structures_in_the_way() {
+		let (mut level, mut entities) = arena(tile(6, 2));
+		let mut kobold = Kobold::new(tile(1, 2));
+		let player = entities.player_id().unwrap();
+
+		for (x, y) in get_adj(6, 2) {
+			place_steel_wall(&mut level, x, y);
+		}
+
+		kobold.state = KoboldState::Hunt {
+			target: player,
+			last_seen: tile(6, 2),
+			memory: 100,
+		};
+
+		for _ in 0..10 {
+			kobold.update(&mut entities, &mut level);
+		}
+
+		assert!(level.structures().any(|s| s.hp() < SteelWall::MAX_HP));
+	}
+
+	#[test]
+	fn saboteurs_go_for_the_most_valuable_machine() {
+		let (mut level, _) = arena(tile(14, 8));
+		let kobold = Kobold::new(tile(1, 2));
+
+		place_steel_wall(&mut level, 3, 2);
+		let generator = footprint(6, 4, DieselGenerator::SIZE);
+		level.add_structure(Box::new(DieselGenerator::new(generator, Orientation::North)));
+
+		assert_eq!(kobold.find_mischief(&level), Some(KoboldState::Sabotage { goal: generator.center() }));
+	}
+
+	#[test]
+	fn unreachable_loot_is_given_up_on() {
+		let (mut level, mut entities) = arena(tile(14, 8));
+		let mut kobold = Kobold::new(tile(1, 2));
+		entities.despawn(entities.player_id().unwrap());
+		entities.flush();
+
+		level.drop_item(tile(8, 2), ItemStack::new(ItemType::Stone, 1));
+		for (x, y) in get_adj(8, 2) {
+			put_wall(&mut level, x, y);
+		}
+
+		kobold.state = KoboldState::Steal { goal: tile(8, 2) };
+
+		assert_eq!(kobold.update(&mut entities, &mut level), Action::Wait);
+		assert_eq!(kobold.state, KoboldState::Wander);
+		assert!(!kobold.can_reach(tile(8, 2)));
+		assert_eq!(kobold.find_mischief(&level), None);
+	}
+
+	#[test]
+	fn saboteurs_find_their_way_round_to_a_reachable_side() {
+		let (mut level, mut entities) = arena(tile(14, 8));
+		let mut kobold = Kobold::new(tile(1, 2));
+		entities.despawn(entities.player_id().unwrap());
+		entities.flush();
+
+		// The west side of the wall is a sealed pocket, even though it's the closest side
+		let rect = place_steel_wall(&mut level, 6, 2);
+		for y in 0..=4 {
+			put_wall(&mut level, 4, y);
+		}
+		for (x, y) in [(5, 0), (5, 4), (6, 1), (6, 3)] {
+			put_wall(&mut level, x, y);
+		}
+
+		kobold.state = KoboldState::Sabotage { goal: rect.center() };
+
+		for _ in 0..20 {
+			kobold.update(&mut entities, &mut level);
+		}
+
+		assert!(level.structure_at(rect.center()).unwrap().hp() < SteelWall::MAX_HP);
+	}
+}
@@ -1,11 +1,14 @@
-use ::rand::Rng;
+use std::collections::HashSet;
+use ::rand::{Rng, SeedableRng, rngs::StdRng};
 use macroquad::prelude::*;
 
 use hot_assets::*;
 
 use crate::primitives::*;
 use crate::structures::*;
+use crate::items::*;
 use crate::types::*;
+use crate::spawning::*;
 
 pub enum WallData {
 	Basic,
@@ -16,6 +19,161 @@ pub enum WallData {
 		hardness: f32,
 		iron_amount: i32,
 	},
+	GoldOre {
+		hardness: f32,
+		gold_amount: i32,
+	},
+	TesiumOre {
+		hardness: f32,
+		tesium_amount: i32,
+	},
+	DeotiumOre {
+		hardness: f32,
+		deotium_amount: i32,
+	},
+	EdyagiteOre {
+		hardness: f32,
+		edyagite_amount: i32,
+	},
+	LashaliteOre {
+		hardness: f32,
+		lashalite_amount: i32,
+	},
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OreType {
+	Iron,
+	Gold,
+	Tesium,
+	Deotium,
+	Edyagite,
+	Lashalite,
+}
+
+impl OreType {
+	pub const ALL: [Self; 6] = [
+		Self::Iron,
+		Self::Gold,
+		Self::Tesium,
+		Self::Deotium,
+		Self::Edyagite,
+		Self::Lashalite,
+	];
+
+	pub fn texture(&self) -> &'static str {
+		match self {
+			Self::Iron => "iron_vein",
+			Self::Gold => "gold_vein",
+			Self::Tesium => "tesium_vein",
+			Self::Deotium => "deotium_vein",
+			Self::Edyagite => "edyagite_vein",
+			Self::Lashalite => "lashalite_vein",
+		}
+	}
+
+	pub fn hardness(&self) -> f32 {
+		match self {
+			Self::Iron => 5.,
+			Self::Gold => 4.,
+			Self::Tesium => 6.,
+			Self::Deotium => 8.,
+			Self::Edyagite => 9.,
+			Self::Lashalite => 12.,
+		}
+	}
+
+	// How many chunks away from the start the ore first shows up
+	pub fn min_depth(&self) -> i32 {
+		match self {
+			Self::Iron => 0,
+			Self::Gold => 1,
+			Self::Tesium => 1,
+			Self::Deotium => 2,
+			Self::Edyagite => 3,
+			Self::Lashalite => 4,
+		}
+	}
+
+	// Veins per chunk once the ore is deep enough, with one more for every chunk past that
+	pub fn veins_per_chunk(&self, depth: i32) -> i32 {
+		let base = match self {
+			Self::Iron => 10,
+			Self::Gold => 3,
+			Self::Tesium => 4,
+			Self::Deotium => 3,
+			Self::Edyagite => 2,
+			Self::Lashalite => 1,
+		};
+
+		if depth < self.min_depth() {
+			0
+		} else {
+			base + (depth - self.min_depth()).min(base)
+		}
+	}
+
+	// Tiles a vein tries to grow into
+	pub fn vein_size(&self) -> std::ops::RangeInclusive<i32> {
+		match self {
+			Self::Iron => 6..=14,
+			Self::Gold => 3..=7,
+			Self::Tesium => 4..=10,
+			Self::Deotium => 4..=8,
+			Self::Edyagite => 3..=6,
+			Self::Lashalite => 2..=4,
+		}
+	}
+
+	// Ore yielded by a single tile of the vein
+	pub fn amount(&self) -> std::ops::RangeInclusive<i32> {
+		match self {
+			Self::Iron => 2..=4,
+			Self::Gold => 1..=3,
+			Self::Tesium => 1..=3,
+			Self::Deotium => 1..=2,
+			Self::Edyagite => 1..=2,
+			Self::Lashalite => 1..=1,
+		}
+	}
+
+	pub fn item(&self) -> ItemType {
+		match self {
+			Self::Iron => ItemType::IronOre,
+			Self::Gold => ItemType::GoldOre,
+			Self::Tesium => ItemType::TesiumOre,
+			Self::Deotium => ItemType::DeotiumOre,
+			Self::Edyagite => ItemType::EdyagiteOre,
+			Self::Lashalite => ItemType::LashaliteOre,
+		}
+	}
+
+	pub fn wall_data(&self, amount: i32) -> WallData {
+		let hardness = self.hardness();
+
+		match self {
+			Self::Iron => WallData::IronOre {hardness, iron_amount: amount},
+			Self::Gold => WallData::GoldOre {hardness, gold_amount: amount},
+			Self::Tesium => WallData::TesiumOre {hardness, tesium_amount: amount},
+			Self::Deotium => WallData::DeotiumOre {hardness, deotium_amount: amount},
+			Self::Edyagite => WallData::EdyagiteOre {hardness, edyagite_amount: amount},
+			Self::Lashalite => WallData::LashaliteOre {hardness, lashalite_amount: amount},
+		}
+	}
+}
+
+impl WallData {
+	pub fn ore(&self) -> Option<OreType> {
+		match self {
+			Self::IronOre {..} => Some(OreType::Iron),
+			Self::GoldOre {..} => Some(OreType::Gold),
+			Self::TesiumOre {..} => Some(OreType::Tesium),
+			Self::DeotiumOre {..} => Some(OreType::Deotium),
+			Self::EdyagiteOre {..} => Some(OreType::Edyagite),
+			Self::LashaliteOre {..} => Some(OreType::Lashalite),
+			_ => None,
+		}
+	}
 }
 
 pub struct Wall {
@@ -41,7 +199,12 @@ impl Wall {
 					false
 				}
 			}
-			WallData::IronOre {ref mut hardness, ..} => {
+			WallData::IronOre {ref mut hardness, ..}
+			| WallData::GoldOre {ref mut hardness, ..}
+			| WallData::TesiumOre {ref mut hardness, ..}
+			| WallData::DeotiumOre {ref mut hardness, ..}
+			| WallData::EdyagiteOre {ref mut hardness, ..}
+			| WallData::LashaliteOre {ref mut hardness, ..} => {
 				*hardness -= amount;
 				if *hardness <= 0. {
 					true
@@ -52,28 +215,64 @@ impl Wall {
 			_ => false
 		}
 	}
+
+	// What's left on the floor once the wall is broken
+	pub fn drops(&self) -> Option<ItemStack> {
+		match self.data {
+			WallData::Basic => None,
+			WallData::CaveWall {..} => Some(ItemStack::new(ItemType::Stone, 1)),
+			WallData::IronOre {iron_amount: amount, ..}
+			| WallData::GoldOre {gold_amount: amount, ..}
+			| WallData::TesiumOre {tesium_amount: amount, ..}
+			| WallData::DeotiumOre {deotium_amount: amount, ..}
+			| WallData::EdyagiteOre {edyagite_amount: amount, ..}
+			| WallData::LashaliteOre {lashalite_amount: amount, ..} => {
+				self.data.ore().map(|ore| ItemStack::new(ore.item(), amount))
+			}
+		}
+	}
 }
 
-struct Decal {
-	pos: Vec2,
-	tex: Texture2D,
-	r_orient: bool,
+pub struct Decal {
+	pub pos: Vec2,
+	pub tex: String,
+	params: DrawTextureParams,
+	color: Color,
 }
 
 impl Decal {
-	pub fn new(pos: Vec2, tex: Texture2D, r_orient: bool) -> Self {
+	pub fn new(pos: Vec2, tex: &str, params: DrawTextureParams, color: Color) -> Self {
 		Self {
-			pos, tex, r_orient
+			pos,
+			tex: tex.to_string(),
+			params,
+			color,
 		}
 	}
 }
 
+// Everything about a chunk that matters to the game is plain data, so it can be generated and
+// played on without a window. The terrain is only drawn into `render_target` the first time
+// the chunk is drawn, and decals are baked on top of it as they come in.
 pub struct Chunk {
-	pub render_target: RenderTarget,
+	pub pos: I16Vec2,
+	pub render_target: Option<RenderTarget>,
+	// One pixel per tile, covering whatever the player can't see right now
+	pub fog: Option<Texture2D>,
+	// Set whenever what the player sees or remembers in here changes, so the fog gets baked again
+	pub fog_stale: bool,
 	pub colliders: Vec<Wall>,
 	pub structures: Vec<Box<dyn Structure>>,
-	assets: *const AssetManager,
-	decals: Vec<Decal>,
+	pub items: Vec<FloorItem>,
+	// true where the cave generated solid rock
+	pub terrain: [[bool; Chunk::SIZE]; Chunk::SIZE],
+	// Monsters from lairs placed at generation, waiting for `populate` to bring them in
+	pub lairs: Vec<(MonsterType, Vec2)>,
+	// Tiles the player has ever had in view
+	pub seen: [[bool; Chunk::SIZE]; Chunk::SIZE],
+	rng: StdRng,
+	// Added since the last bake and still waiting to be drawn into the render target
+	pub decals: Vec<Decal>,
 }
 
 pub fn get_adj(x: i32, y: i32) -> Vec<(i32, i32)> {
@@ -91,27 +290,23 @@ pub fn get_adj(x: i32, y: i32) -> Vec<(i32, i32)> {
 
 impl Chunk {
 	pub const SIZE: usize = 64;
+	pub const PIXEL_SIZE: f32 = Self::SIZE as f32 * T_SIZE;
+	pub const SHROOM_CHANCE: f64 = 0.15;
 
-	fn get_wall_at(&self, _pos: Vec2) -> Texture2D {
-		unsafe {
-			(&*self.assets).images["stone_wall"].clone()
-		}
+	fn get_wall_at(&self, _pos: Vec2) -> &'static str {
+		"stone_wall"
 	}
 
-	fn get_floor_at(&self, pos: Vec2) -> Texture2D {
-		unsafe {
-			if self.get_moisture_at(pos) > 0.1 {
-				(&*self.assets).images["cave_soil_floor"].clone()	
-			} else {
-				(&*self.assets).images["stone_floor"].clone()
-			}
+	fn get_floor_at(&self, pos: Vec2) -> &'static str {
+		if self.get_moisture_at(pos) > 0.1 {
+			"cave_soil_floor"
+		} else {
+			"stone_floor"
 		}
 	}
 
-	fn get_rubble_at(&self, _pos: Vec2) -> Texture2D {
-		unsafe {
-			(&*self.assets).images["stone_rubble"].clone()
-		}
+	fn get_rubble_at(&self, _pos: Vec2) -> &'static str {
+		"stone_rubble"
 	}
 
 	fn get_moisture_at(&self, _pos: Vec2) -> f64 {
@@ -119,83 +314,153 @@ impl Chunk {
 	}
 
 	pub fn damage_terrain(&mut self, index: usize, amount: f32) {
-		let mut rng = ::rand::thread_rng();
+		if self.colliders[index].damage(amount) {
+			let pos = self.colliders[index].rect.point();
+			self.add_decal(pos, self.get_rubble_at(pos), false);
 
-		unsafe {
-			if self.colliders[index].damage(amount) {
-				self.add_decal(self.colliders[index].rect.point(), self.get_rubble_at(self.colliders[index].rect.point()), false);
-				self.colliders.remove(index);
-			} else {
-				self.add_decal(self.colliders[index].rect.point(), (&*self.assets).images[&format!("crack.{}", rng.gen_range(0..=3))].clone(), true);
+			if let Some(stack) = self.colliders[index].drops() {
+				self.drop_item(pos, stack);
 			}
+
+			let (x, y) = self.local_tile(pos);
+			self.terrain[y][x] = false;
+			self.colliders.remove(index);
+		} else {
+			let crack = format!("crack.{}", self.rng.gen_range(0..=3));
+			self.add_decal(self.colliders[index].rect.point(), &crack, true);
+		}
+	}
+
+	pub fn local_tile(&self, pos: Vec2) -> (usize, usize) {
+		let tile = ((pos - self.origin()) / T_SIZE).floor();
+		(tile.x as usize, tile.y as usize)
+	}
+
+	// Drops onto a tile pile up into one stack per item type
+	pub fn drop_item(&mut self, pos: Vec2, stack: ItemStack) {
+		if let Some(floor_item) = self.items.iter_mut().find(|i| i.pos == pos && i.stack.item == stack.item) {
+			floor_item.stack.amount += stack.amount;
+		} else {
+			self.items.push(FloorItem::new(pos, stack));
 		}
 	}
 
-	pub fn add_decal(&mut self, pos: Vec2, tex: Texture2D, r_orient: bool) {
-		self.decals.push(Decal::new(pos, tex, r_orient));
+	pub fn take_items_at(&mut self, pos: Vec2) -> Vec<ItemStack> {
+		let mut taken = Vec::new();
+
+		self.items.retain(|i| {
+			if i.pos == pos {
+				taken.push(i.stack);
+				false
+			} else {
+				true
+			}
+		});
+
+		taken
+	}
+
+	pub fn add_rubble(&mut self, pos: Vec2) {
+		self.add_decal(pos, self.get_rubble_at(pos), false);
 	}
 
-	pub fn new(assets: &AssetManager) -> Self {
-		let target = render_target(Self::SIZE as u32 * T_SIZE as u32, Self::SIZE as u32 * T_SIZE as u32);
-		target.texture.set_filter(FilterMode::Nearest);
+	// Whatever died here, lying on its side and greyed out, baked into the floor for good
+	pub fn add_corpse(&mut self, pos: Vec2, tex: &str) {
+		let params = DrawTextureParams {
+			rotation: PI_H,
+			flip_x: self.rng.gen(),
+			..Default::default()
+		};
+
+		self.decals.push(Decal::new(pos, tex, params, Color::new(0.45, 0.4, 0.4, 1.)));
+	}
 
+	// Randomly oriented decals pick their orientation now, so drawing never touches the rng
+	pub fn add_decal(&mut self, pos: Vec2, tex: &str, r_orient: bool) {
+		let params = if r_orient {
+			DrawTextureParams {
+				flip_x: self.rng.gen(),
+				flip_y: self.rng.gen(),
+				rotation: self.rng.gen_range(0..4) as f32 * PI_H,
+				..Default::default()
+			}
+		} else {
+			DrawTextureParams {
+				..Default::default()
+			}
+		};
+
+		self.decals.push(Decal::new(pos, tex, params, WHITE));
+	}
+
+	pub fn origin(&self) -> Vec2 {
+		self.pos.as_vec2() * Self::PIXEL_SIZE
+	}
+
+	pub fn bounds(&self) -> Rect {
+		let origin = self.origin();
+		Rect::new(origin.x, origin.y, Self::PIXEL_SIZE, Self::PIXEL_SIZE)
+	}
+
+	// Chebyshev distance from the starting chunk
+	pub fn depth(&self) -> i32 {
+		(self.pos.x as i32).abs().max((self.pos.y as i32).abs())
+	}
+
+	pub fn wall_index_at(&self, pos: Vec2) -> Option<usize> {
+		self.colliders.iter().position(|w: &Wall| w.rect.point() == pos)
+	}
+
+	pub fn new(pos: I16Vec2, seed: u64) -> Self {
 		Self {
-			render_target: target,
+			pos,
+			render_target: None,
+			fog: None,
+			fog_stale: true,
 			colliders: Vec::new(),
 			structures: Vec::new(),
-			assets: assets as *const AssetManager,
+			items: Vec::new(),
+			terrain: [[false; Self::SIZE]; Self::SIZE],
+			lairs: Vec::new(),
+			seen: [[false; Self::SIZE]; Self::SIZE],
+			rng: StdRng::seed_from_u64(seed),
 			decals: Vec::new(),
 		}
 	}
 
-	pub fn generate(&mut self) {
+	pub fn generate(&mut self, is_rock: impl Fn(IVec2) -> bool) {
 		self.colliders = Vec::new();
 		self.decals = Vec::new();
-		self.render_target = render_target(Self::SIZE as u32 * T_SIZE as u32, Self::SIZE as u32 * T_SIZE as u32);
-		self.render_target.texture.set_filter(FilterMode::Nearest);
+		self.items = Vec::new();
+		self.render_target = None;
 
-		let mut terrain = [[false; Self::SIZE]; Self::SIZE];
-		let percent = 45;
 		let smooth_iterations = 2;
 
-		let mut rng = ::rand::thread_rng();
+		// Every smoothing pass needs one ring of neighbours, so the starting noise is sampled
+		// that far past each edge. Since `is_rock` only depends on the world tile, the padding
+		// is exactly what the neighbouring chunks start from and the caves line up at the seams.
+		let pad = smooth_iterations;
+		let padded_size = Self::SIZE + pad * 2;
+		let first_tile = self.pos.as_ivec2() * Self::SIZE as i32 - IVec2::splat(pad as i32);
 
-		for x in 0..Self::SIZE {
-			for y in 0..Self::SIZE {
-				terrain[y][x] = if rng.gen_range(0..100) >= percent {
-					false
-				} else {
-					true
-				};
+		let mut terrain = vec![vec![false; padded_size]; padded_size];
+
+		for (y, row) in terrain.iter_mut().enumerate() {
+			for (x, rock) in row.iter_mut().enumerate() {
+				*rock = is_rock(first_tile + ivec2(x as i32, y as i32));
 			}
 		}
 
-		let cam_scale_factor = (Self::SIZE / 2) as f32 * T_SIZE;
-		set_camera(&Camera2D {
-            zoom: vec2(1. / cam_scale_factor, 1. / cam_scale_factor),
-            target: vec2(Self::SIZE as f32 * T_SIZE / 2., Self::SIZE as f32 * T_SIZE / 2.),
-            render_target: Some(self.render_target.clone()),
-            ..Default::default()
-        });
-
 		for _ in 0..smooth_iterations {
-			for x in 0..Self::SIZE {
-				for y in 0..Self::SIZE {
-					let t_pos = vec2(x as f32 * T_SIZE, y as f32 * T_SIZE);
-					if x == 0 && y == 0 {
-						let comp_wall = Wall::new(t_pos, WallData::Basic);
-						if self.colliders.iter().position(|w: &Wall| w.rect == comp_wall.rect).is_none() {
-							draw_texture(&self.get_floor_at(t_pos), t_pos.x, t_pos.y, WHITE);							
-						}
-						continue;
-					}
+			let mut smoothed = terrain.clone();
 
+			for (y, row) in smoothed.iter_mut().enumerate() {
+				for (x, rock) in row.iter_mut().enumerate() {
 					let mut n = 0;
 
 					for (ax, ay) in get_adj(x as i32, y as i32) {
-						// TODO: Change this when factoring in other chunks
-						if ax < 0 || ax >= Self::SIZE as i32 || ay < 0 || ay >= Self::SIZE as i32 {
-							n += rng.gen_range(0..=3);
+						// The outermost ring is thrown away when cropping, so whatever it does here doesn't matter
+						if ax < 0 || ax >= padded_size as i32 || ay < 0 || ay >= padded_size as i32 {
 							continue;
 						}
 
@@ -204,31 +469,112 @@ impl Chunk {
 						}
 					}
 
-					if n >= 4 {
-						// Floor
-						let comp_wall = Wall::new(t_pos, WallData::Basic);
-						if self.colliders.iter().position(|w: &Wall| w.rect == comp_wall.rect).is_none() {
-							draw_texture(&self.get_floor_at(t_pos), t_pos.x, t_pos.y, WHITE);							
-						}
-					} else if n < 4 {
-						// Wall
-						let comp_wall = Wall::new(t_pos, WallData::Basic);
-						draw_texture(&self.get_wall_at(t_pos), t_pos.x, t_pos.y, WHITE);
-						if self.colliders.iter().position(|w: &Wall| w.rect == comp_wall.rect).is_none() {
-							self.colliders.push(Wall::new(t_pos, WallData::CaveWall {
-								hardness: 3.,
-							}));
-						}
+					*rock = n < 4;
+				}
+			}
+
+			terrain = smoothed;
+		}
+
+		for x in 0..Self::SIZE {
+			for y in 0..Self::SIZE {
+				self.terrain[y][x] = terrain[y + pad][x + pad];
+			}
+		}
+
+		// Keep the spawn point open
+		if self.pos == I16Vec2::ZERO {
+			self.terrain[0][0] = false;
+		}
+
+		let veins = self.place_veins();
+		self.place_shrooms();
+
+		let origin = self.origin();
+
+		self.lairs = place_lairs(&self.terrain, self.depth(), &mut self.rng).into_iter()
+			.map(|(monster, (x, y))| (monster, origin + vec2(x as f32 * T_SIZE, y as f32 * T_SIZE)))
+			.collect();
+
+		for (y, row) in self.terrain.iter().enumerate() {
+			for (x, &rock) in row.iter().enumerate() {
+				if !rock {
+					continue;
+				}
+
+				let t_pos = origin + vec2(x as f32 * T_SIZE, y as f32 * T_SIZE);
+
+				if let Some((ore, amount)) = veins[y][x] {
+					self.colliders.push(Wall::new(t_pos, ore.wall_data(amount)));
+				} else {
+					self.colliders.push(Wall::new(t_pos, WallData::CaveWall {
+						hardness: 3.,
+					}));
+				}
+			}
+		}
+	}
+
+	// Grows clusters of ore through the rock with short random walks. Only rock tiles are
+	// turned into ore, so veins hug the cave walls instead of floating in open space.
+	fn place_veins(&mut self) -> Vec<Vec<Option<(OreType, i32)>>> {
+		let mut veins = vec![vec![None; Self::SIZE]; Self::SIZE];
+		let depth = self.depth();
+
+		for ore in OreType::ALL {
+			for _ in 0..ore.veins_per_chunk(depth) {
+				let mut x = self.rng.gen_range(0..Self::SIZE as i32);
+				let mut y = self.rng.gen_range(0..Self::SIZE as i32);
+				let size = self.rng.gen_range(ore.vein_size());
+
+				for _ in 0..size {
+					if self.terrain[y as usize][x as usize] && veins[y as usize][x as usize].is_none() {
+						veins[y as usize][x as usize] = Some((ore, self.rng.gen_range(ore.amount())));
 					}
+
+					let (nx, ny) = get_adj(x, y)[self.rng.gen_range(0..8)];
+					x = nx.clamp(0, Self::SIZE as i32 - 1);
+					y = ny.clamp(0, Self::SIZE as i32 - 1);
 				}
 			}
 		}
 
-		set_default_camera();
+		veins
+	}
+
+	// Latala shrooms like the nooks of the cave, so they only grow on floor mostly closed in by rock
+	fn place_shrooms(&mut self) {
+		let origin = self.origin();
+
+		for x in 0..Self::SIZE {
+			for y in 0..Self::SIZE {
+				if self.terrain[y][x] {
+					continue;
+				}
+
+				let walls = get_adj(x as i32, y as i32).into_iter()
+					.filter(|&(ax, ay)| {
+						ax >= 0 && ax < Self::SIZE as i32 && ay >= 0 && ay < Self::SIZE as i32
+							&& self.terrain[ay as usize][ax as usize]
+					})
+					.count();
+
+				if walls >= 5 && self.rng.gen_bool(Self::SHROOM_CHANCE) {
+					let amount = self.rng.gen_range(1..=3);
+					let pos = origin + vec2(x as f32 * T_SIZE, y as f32 * T_SIZE);
+					self.items.push(FloorItem::new(pos, ItemStack::new(ItemType::LatalaShroom, amount)));
+				}
+			}
+		}
 	}
 
-	pub fn draw(&mut self) {
-		draw_texture(&self.render_target.texture, 0., 0., WHITE);
+	pub fn draw(&self) {
+		let Some(target) = &self.render_target else {
+			return;
+		};
+
+		let origin = self.origin();
+		draw_texture(&target.texture, origin.x, origin.y, WHITE);
 		// for collider in &self.colliders {
 		// 	draw_rectangle_lines(collider.rect.x, collider.rect.y, collider.rect.w, collider.rect.h, 1., RED);
 		// 	match collider.data {
@@ -238,40 +584,114 @@ impl Chunk {
 		// 		_ => {}
 		// 	}
 		// }
-		for structure in &mut self.structures {
-			structure.draw();
+	}
+
+	// Clear where the player can see, dimmed where they only remember, black where they've never looked
+	pub fn bake_fog(&mut self, visible: &HashSet<IVec2>) {
+		if self.fog.is_some() && !self.fog_stale {
+			return;
+		}
+
+		let mut mask = Image::gen_image_color(Self::SIZE as u16, Self::SIZE as u16, BLACK);
+		let origin = self.pos.as_ivec2() * Self::SIZE as i32;
+
+		for x in 0..Self::SIZE {
+			for y in 0..Self::SIZE {
+				let color = if visible.contains(&(origin + ivec2(x as i32, y as i32))) {
+					BLANK
+				} else if self.seen[y][x] {
+					Color::new(0., 0., 0., 0.6)
+				} else {
+					continue;
+				};
+
+				mask.set_pixel(x as u32, y as u32, color);
+			}
 		}
+
+		match &self.fog {
+			Some(fog) => fog.update(&mask),
+			None => {
+				let fog = Texture2D::from_image(&mask);
+				fog.set_filter(FilterMode::Nearest);
+				self.fog = Some(fog);
+			}
+		}
+
+		self.fog_stale = false;
 	}
 
-	pub fn update(&mut self) {
+	pub fn draw_fog(&self) {
+		let Some(fog) = &self.fog else {
+			return;
+		};
+
+		let origin = self.origin();
+		draw_texture_ex(fog, origin.x, origin.y, WHITE, DrawTextureParams {
+			dest_size: Some(vec2(Self::PIXEL_SIZE, Self::PIXEL_SIZE)),
+			..Default::default()
+		});
+	}
+
+	pub fn draw_items(&self, assets: &AssetManager) {
+		for item in &self.items {
+			item.draw(assets);
+		}
+	}
+
+	pub fn draw_structures(&mut self, view: Rect, assets: &AssetManager) {
+		for structure in self.structures.iter_mut().filter(|s| s.rect().overlaps(&view)) {
+			structure.draw(assets);
+		}
+	}
+
+	// Draws the terrain into the render target the first time round, then any decals added since
+	pub fn bake(&mut self, assets: &AssetManager) {
+		if self.render_target.is_some() && self.decals.is_empty() {
+			return;
+		}
+
 		let cam_scale_factor = (Self::SIZE / 2) as f32 * T_SIZE;
+		let fresh = self.render_target.is_none();
+		let target = self.render_target.get_or_insert_with(|| {
+			let target = render_target(Self::SIZE as u32 * T_SIZE as u32, Self::SIZE as u32 * T_SIZE as u32);
+			target.texture.set_filter(FilterMode::Nearest);
+			target
+		}).clone();
 
 		set_camera(&Camera2D {
             zoom: vec2(1. / cam_scale_factor, 1. / cam_scale_factor),
             target: vec2(Self::SIZE as f32 * T_SIZE / 2., Self::SIZE as f32 * T_SIZE / 2.),
-            render_target: Some(self.render_target.clone()),
+            render_target: Some(target),
             ..Default::default()
         });
 
-        let mut rng = ::rand::thread_rng();
+        let origin = self.origin();
 
-		for decal in &self.decals {
-			let params = if decal.r_orient {
-				DrawTextureParams {
-					flip_x: rng.gen(),
-					flip_y: rng.gen(),
-					rotation: rng.gen_range(0..4) as f32 * PI_H,
-					..Default::default()
+		if fresh {
+			for x in 0..Self::SIZE {
+				for y in 0..Self::SIZE {
+					let l_pos = vec2(x as f32 * T_SIZE, y as f32 * T_SIZE);
+					draw_texture(&assets.images[self.get_floor_at(origin + l_pos)], l_pos.x, l_pos.y, WHITE);
 				}
-			} else {
-				DrawTextureParams {
-					..Default::default()
+			}
+
+			for wall in &self.colliders {
+				let l_pos = wall.rect.point() - origin;
+				draw_texture(&assets.images[self.get_wall_at(wall.rect.point())], l_pos.x, l_pos.y, WHITE);
+
+				if let Some(ore) = wall.data.ore() {
+					draw_texture(&assets.images[ore.texture()], l_pos.x, l_pos.y, WHITE);
 				}
-			};
+			}
+		}
+
+		for decal in &self.decals {
+			let l_pos = decal.pos - origin;
 
 			draw_texture_ex(
-				&decal.tex, decal.pos.x, decal.pos.y, WHITE,
-				params
+				&assets.images[&decal.tex], l_pos.x, l_pos.y, decal.color,
+				decal.params.clone()
 			);
 		}
 
@@ -279,4 +699,4 @@ impl Chunk {
 
         set_default_camera();
 	}
-}
\ No newline at end of file
+}
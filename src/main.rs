@@ -1,15 +1,19 @@
+use ::rand::Rng;
 use macroquad::prelude::*;
 
 use hot_assets::*;
 
-use crate::level::*;
+use crate::generation::*;
 use crate::primitives::*;
 use crate::player::*;
 use crate::kobold::*;
 use crate::structures::*;
 use crate::menus::*;
+use crate::scheduler::*;
+use crate::entities::*;
 
 mod level;
+mod generation;
 mod primitives;
 mod player;
 mod a_star;
@@ -17,6 +21,16 @@ mod kobold;
 mod types;
 mod structures;
 mod menus;
+mod items;
+mod power;
+mod recipes;
+mod scheduler;
+mod entities;
+mod combat;
+mod spawning;
+mod fov;
+#[cfg(test)]
+mod test_utils;
 
 fn conf() -> Conf {
     Conf {
@@ -28,31 +42,108 @@ fn conf() -> Conf {
 
 const MIN_FRAME_TIME: f32 = 1. / 60.;
 
+// `--seed <seed>` on the command line wins over the ROGUELIKE_SEED environment variable,
+// and without either a random one is picked
+fn get_seed() -> u64 {
+    let args = std::env::args().collect::<Vec<String>>();
+
+    if let Some(i) = args.iter().position(|a| a == "--seed") {
+        if let Some(seed) = args.get(i + 1) {
+            return parse_seed(seed);
+        }
+    }
+
+    if let Ok(seed) = std::env::var("ROGUELIKE_SEED") {
+        return parse_seed(&seed);
+    }
+
+    ::rand::thread_rng().gen()
+}
+
 #[macroquad::main(conf)]
 async fn main () {
     let assets = AssetManager::new().await;
 
-    let mut chunk = Chunk::new(&assets);
-    chunk.generate();
+    let seed = get_seed();
+    let mut level = Level::new(seed);
 
     let mut last_mouse_position = mouse_position();
     let mut camera_target = vec2(0., 0.);
     let mut zoom = 0.001;
 
-    let mut global_id = 0;
-    let mut entities = Vec::<Box<dyn Entity>>::new();
+    let mut entities = Entities::new();
+    entities.spawn(Box::new(Player::new(vec2(0., 0.))));
+    entities.flush();
+
+    let mut scheduler = Scheduler::new();
+    let mut show_power = false;
+
+    loop {
+        // Input
+        let mut command = None;
+        let player_id = entities.player_id();
+
+        if let Some(player) = player_id.and_then(|id| entities.get_mut(id)) {
+            command = player.input(&level);
+
+            if let Some(player) = player.as_player_mut() {
+                if let Some(mut menu) = player.menu.take() {
+                    let result = menu.run().await;
 
-    macro_rules! add_entity {
-        ($entity:expr) => {
-            $entity.id = global_id;
-            global_id += 1;
-            entities.push(Box::new($entity));
+                    player.selected_blueprint = result;
+                    let mut blueprint = Blueprint::new(player.rect.point(), result);
+                    blueprint.update_valid(&level, &player.inventory);
+                    player.blueprint = Some(blueprint);
+                }
+
+                if let Some(mut inventory_menu) = player.inventory_menu.take() {
+                    if let Some(stack) = inventory_menu.run(&player.inventory).await {
+                        command = Some(Command::Drop(stack));
+                    }
+                }
+
+                if let Some(mut structure_menu) = player.structure_menu.take() {
+                    if let Some(structure) = level.structure_at(structure_menu.pos) {
+                        if let Some(transfer) = structure_menu.run(structure, &player.inventory, &assets).await {
+                            command = Some(transfer);
+                            // Back open next frame, once the transfer has gone through
+                            player.structure_menu = Some(structure_menu);
+                        }
+                    }
+                }
+            }
         }
-    }
 
-    add_entity!(Player::new(vec2(0., 0.), &assets));
+        if is_key_pressed(KeyCode::P) {
+            show_power = !show_power;
+        }
 
-    loop {
+        // Simulation
+        if let (Some(id), Some(command)) = (player_id, command) {
+            if let Some(mut player) = entities.take(id) {
+                let action = player.act(command, &mut entities, &mut level);
+
+                if let Some(action) = action {
+                    player.energy_mut().spend(action);
+                }
+
+                entities.put_back(player);
+                entities.reap(&mut level);
+                entities.flush();
+
+                if action.is_some() {
+                    scheduler.advance(&mut entities, &mut level);
+                }
+            }
+        }
+
+        if let Some(player) = player_id.and_then(|id| entities.get(id)) {
+            camera_target = player.rect().center();
+            level.stream(player.rect().center());
+            level.update_fov(player.rect().center(), Player::SIGHT_RANGE);
+        }
+
+        // Rendering
         clear_background(BLACK);
 
         let camera = Camera2D {
@@ -64,41 +155,14 @@ async fn main () {
 
         set_camera(&camera);
 
-        chunk.draw();
-
-        let mut update = false;
-        let entities_ptr = &mut entities as *mut Vec<Box<dyn Entity>>;
-        for i in 0..entities.len() {
-            entities[i].draw(entities_ptr, &mut chunk);
-
-            unsafe {
-                match entities[i].data() {
-                    EntityData::Player {end_turn, rect, ref mut obj, ..} => {
-                        if let Some(ref mut menu) = (**obj).menu {
-                            let result = menu.run().await;
-                            set_camera(&camera);
+        level.draw(&camera, &assets);
 
-                            (**obj).selected_blueprint = result;
-                            let blueprint = Blueprint::new((**obj).rect.point(), &assets, result);
-                            (**obj).blueprint = Some(blueprint);
-
-                            (**obj).menu = None;
-                        }
-
-                        camera_target = rect.center();
-                        if end_turn {
-                            update = true;
-                        }
-                    }
-                    _ => {}
-                }
-            }
+        for entity in entities.iter().filter(|e| level.is_visible(e.rect().center())) {
+            entity.draw(&level, &assets);
         }
 
-        if update {
-            for i in 0..entities.len() {
-                entities[i].update(entities_ptr, &mut chunk);
-            }
+        if show_power {
+            level.draw_power_grids();
         }
 
         let mouse_rel = Vec2::from_array(last_mouse_position.into()) - Vec2::from_array(mouse_position().into());
@@ -113,10 +177,17 @@ async fn main () {
 
         last_mouse_position = mouse_position();
 
-        chunk.update();
-
         set_default_camera();
 
+        draw_text(&format!("Seed: {}", level.seed), 10., 20., 20., WHITE);
+        draw_text(&format!("Turn: {}", scheduler.turn()), 10., 40., 20., WHITE);
+
+        if let Some(player) = player_id.and_then(|id| entities.get(id)) {
+            draw_text(&format!("HP: {}/{}", player.hp(), player.max_hp()), 10., 60., 20., WHITE);
+        } else {
+            draw_text("You have died", 10., 60., 20., RED);
+        }
+
         let frame_time = get_frame_time();
         if frame_time < MIN_FRAME_TIME {
             std::thread::sleep(std::time::Duration::from_secs_f32(MIN_FRAME_TIME - frame_time));
@@ -1,7 +1,11 @@
 use macroquad::prelude::*;
 
+use hot_assets::*;
+
 use crate::structures::*;
 use crate::primitives::*;
+use crate::items::*;
+use crate::types::*;
 
 const TEXT_PADDING: Vec2 = vec2(T_SIZE, T_SIZE);
 
@@ -13,6 +17,7 @@ impl Menu {
 			Crusher::NAME,
 			ArcFurnace::NAME,
 			SteelWall::NAME,
+			Conveyor::NAME,
 		];
 
 		let options = vec![
@@ -20,6 +25,7 @@ impl Menu {
 			BlueprintType::Crusher,
 			BlueprintType::ArcFurnace,
 			BlueprintType::SteelWall,
+			BlueprintType::Conveyor,
 		];
 
 		let mut cursor_index = 0;
@@ -75,4 +81,296 @@ impl Menu {
 
 		current_option
 	}
-}
\ No newline at end of file
+}
+
+pub struct InventoryMenu;
+impl InventoryMenu {
+	// Returns the stack the player chose to drop, if any
+	pub async fn run(&mut self, inventory: &Inventory) -> Option<ItemStack> {
+		let mut cursor_index = 0;
+
+		set_default_camera();
+
+		loop {
+			clear_background(BLACK);
+
+			if is_key_pressed(KeyCode::Escape) {
+				return None;
+			}
+
+			if !inventory.stacks.is_empty() {
+				if is_key_pressed(KeyCode::Kp2) || is_key_pressed(KeyCode::Down) {
+					if cursor_index == inventory.stacks.len() - 1 {
+						cursor_index = 0;
+					} else {
+						cursor_index += 1;
+					}
+				}
+
+				if is_key_pressed(KeyCode::Kp8) || is_key_pressed(KeyCode::Up) {
+					if cursor_index == 0 {
+						cursor_index = inventory.stacks.len() - 1;
+					} else {
+						cursor_index -= 1;
+					}
+				}
+
+				if is_key_pressed(KeyCode::D) {
+					return Some(inventory.stacks[cursor_index]);
+				}
+			}
+
+			draw_text(
+				&format!("Inventory ({:.0} / {:.0})", inventory.weight(), inventory.capacity),
+				TEXT_PADDING.x, TEXT_PADDING.y, T_SIZE, WHITE
+			);
+
+			for (i, stack) in inventory.stacks.iter().enumerate() {
+				let color = if i == cursor_index {
+					WHITE
+				} else {
+					LIGHTGRAY
+				};
+
+				let y = TEXT_PADDING.y + ((i + 1) as f32 * T_SIZE);
+				draw_rectangle(TEXT_PADDING.x * 2., y - T_SIZE / 2., T_SIZE / 2., T_SIZE / 2., stack.item.color());
+				draw_text(&format!("{} x{}", stack.item.name(), stack.amount), TEXT_PADDING.x * 3., y, T_SIZE, color);
+			}
+
+			if inventory.stacks.is_empty() {
+				draw_text("Empty", TEXT_PADDING.x * 2., TEXT_PADDING.y + T_SIZE, T_SIZE, LIGHTGRAY);
+			} else {
+				draw_text(">", TEXT_PADDING.x, TEXT_PADDING.y + ((cursor_index + 1) as f32 * T_SIZE), T_SIZE, WHITE);
+			}
+
+			draw_text("[D] Drop  [Esc] Close", TEXT_PADDING.x, screen_height() - TEXT_PADDING.y, T_SIZE / 2., LIGHTGRAY);
+
+			next_frame().await;
+		}
+	}
+}
+
+// Only ever looks at the structure and inventory. Moving items is handed back as a command, and
+// the menu is opened again once the simulation has carried it out.
+pub struct StructureMenu {
+	// Any point inside the structure being looked at
+	pub pos: Vec2,
+	pane: StructurePane,
+	slot_index: usize,
+	stack_index: usize,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum StructurePane {
+	Slots,
+	Inventory,
+}
+
+impl StructureMenu {
+	const COLUMN_WIDTH: f32 = T_SIZE * 10.;
+
+	pub fn new(pos: Vec2) -> Self {
+		Self {
+			pos,
+			pane: StructurePane::Slots,
+			slot_index: 0,
+			stack_index: 0,
+		}
+	}
+
+	// Runs until the player closes it, or asks to move something between the two sides
+	pub async fn run(&mut self, structure: &dyn Structure, inventory: &Inventory, assets: &AssetManager) -> Option<Command> {
+		set_default_camera();
+
+		loop {
+			clear_background(BLACK);
+
+			if is_key_pressed(KeyCode::Escape) {
+				return None;
+			}
+
+			if is_key_pressed(KeyCode::Kp4) || is_key_pressed(KeyCode::Left) {
+				self.pane = StructurePane::Slots;
+			}
+
+			if is_key_pressed(KeyCode::Kp6) || is_key_pressed(KeyCode::Right) {
+				self.pane = StructurePane::Inventory;
+			}
+
+			let len = match self.pane {
+				StructurePane::Slots => structure.slots().len(),
+				StructurePane::Inventory => inventory.stacks.len(),
+			};
+
+			let cursor_index = match self.pane {
+				StructurePane::Slots => &mut self.slot_index,
+				StructurePane::Inventory => &mut self.stack_index,
+			};
+
+			if len > 0 {
+				if is_key_pressed(KeyCode::Kp2) || is_key_pressed(KeyCode::Down) {
+					if *cursor_index >= len - 1 {
+						*cursor_index = 0;
+					} else {
+						*cursor_index += 1;
+					}
+				}
+
+				if is_key_pressed(KeyCode::Kp8) || is_key_pressed(KeyCode::Up) {
+					if *cursor_index == 0 || *cursor_index >= len {
+						*cursor_index = len - 1;
+					} else {
+						*cursor_index -= 1;
+					}
+				}
+			}
+
+			if is_key_pressed(KeyCode::Enter) {
+				if let Some(command) = self.transfer(structure, inventory) {
+					return Some(command);
+				}
+			}
+
+			self.draw(structure, inventory, assets);
+
+			next_frame().await;
+		}
+	}
+
+	fn transfer(&self, structure: &dyn Structure, inventory: &Inventory) -> Option<Command> {
+		match self.pane {
+			// Anything in a slot can be taken back out
+			StructurePane::Slots => {
+				structure.slots().get(self.slot_index)?.stack?;
+
+				Some(Command::Transfer {
+					pos: self.pos,
+					slot: self.slot_index,
+					stack: None,
+				})
+			}
+			// Goes into the highlighted slot if it fits there, otherwise the first input that takes it
+			StructurePane::Inventory => {
+				let stack = inventory.stacks.get(self.stack_index).copied()?;
+				let slots = structure.slots();
+				let takes = |s: &Slot| (s.kind == SlotKind::Input || s.kind == SlotKind::Belt) && s.accepts(stack.item);
+				let highlighted = slots.get(self.slot_index).map(takes).unwrap_or(false);
+
+				let target = if highlighted {
+					self.slot_index
+				} else {
+					slots.iter().position(takes)?
+				};
+
+				let amount = stack.amount.min(slots[target].space_for(stack.item));
+
+				(amount > 0).then_some(Command::Transfer {
+					pos: self.pos,
+					slot: target,
+					stack: Some(ItemStack::new(stack.item, amount)),
+				})
+			}
+		}
+	}
+
+	fn draw(&self, structure: &dyn Structure, inventory: &Inventory, assets: &AssetManager) {
+		let mut y = TEXT_PADDING.y;
+
+		draw_text(structure.name(), TEXT_PADDING.x, y, T_SIZE, WHITE);
+
+		if let Some(tex) = structure.interface_tex().map(|t| &assets.images[t]) {
+			draw_texture(tex, screen_width() - TEXT_PADDING.x - tex.width(), TEXT_PADDING.y / 2., WHITE);
+		}
+
+		y += T_SIZE * 0.75;
+		draw_text(&structure.status(), TEXT_PADDING.x, y, T_SIZE / 2., LIGHTGRAY);
+
+		y += T_SIZE * 0.5;
+		draw_text(&format!("HP: {}", structure.hp()), TEXT_PADDING.x, y, T_SIZE / 2., LIGHTGRAY);
+
+		if let Some(power) = structure.power() {
+			y += T_SIZE * 0.5;
+			let text = if power.output > 0. {
+				format!("Power: {:.0}W output, {:.0}% used", power.output, power.load * 100.)
+			} else {
+				format!("Power: {:.0}W demand, {:.0}% supplied", power.demand, power.satisfaction * 100.)
+			};
+			draw_text(&text, TEXT_PADDING.x, y, T_SIZE / 2., LIGHTGRAY);
+		}
+
+		if let Some(progress) = structure.progress() {
+			y += T_SIZE * 0.25;
+			let width = Self::COLUMN_WIDTH - TEXT_PADDING.x;
+			draw_rectangle(TEXT_PADDING.x, y, width * progress.clamp(0., 1.), T_SIZE / 4., GREEN);
+			draw_rectangle_lines(TEXT_PADDING.x, y, width, T_SIZE / 4., 2., WHITE);
+			y += T_SIZE / 4.;
+		}
+
+		y += T_SIZE * 1.5;
+
+		// Machine slots on the left, the player's inventory on the right
+		let columns = [
+			(
+				"Slots",
+				TEXT_PADDING.x,
+				structure.slots().iter().map(|slot| {
+					let contents = if let Some(stack) = slot.stack {
+						format!("{} x{}", stack.item.name(), stack.amount)
+					} else {
+						"Empty".to_string()
+					};
+					(format!("{}: {}", slot.name, contents), slot.stack.map(|s| s.item.color()))
+				}).collect::<Vec<(String, Option<Color>)>>(),
+				self.pane == StructurePane::Slots,
+				self.slot_index,
+			),
+			(
+				"Inventory",
+				TEXT_PADDING.x + Self::COLUMN_WIDTH,
+				inventory.stacks.iter().map(|stack| {
+					(format!("{} x{}", stack.item.name(), stack.amount), Some(stack.item.color()))
+				}).collect::<Vec<(String, Option<Color>)>>(),
+				self.pane == StructurePane::Inventory,
+				self.stack_index,
+			),
+		];
+
+		for (title, x, rows, focused, cursor_index) in columns {
+			let title_color = if focused {
+				WHITE
+			} else {
+				GRAY
+			};
+
+			draw_text(title, x, y, T_SIZE / 1.5, title_color);
+
+			if rows.is_empty() {
+				draw_text("Empty", x + T_SIZE, y + T_SIZE, T_SIZE / 2., GRAY);
+			}
+
+			for (i, (text, color)) in rows.iter().enumerate() {
+				let row_y = y + ((i + 1) as f32 * T_SIZE * 0.75);
+				let text_color = if focused && i == cursor_index {
+					WHITE
+				} else {
+					LIGHTGRAY
+				};
+
+				if let Some(color) = color {
+					draw_rectangle(x + T_SIZE / 2., row_y - T_SIZE / 3., T_SIZE / 3., T_SIZE / 3., *color);
+				}
+
+				draw_text(text, x + T_SIZE, row_y, T_SIZE / 2., text_color);
+
+				if focused && i == cursor_index {
+					draw_text(">", x, row_y, T_SIZE / 2., WHITE);
+				}
+			}
+		}
+
+		draw_text(
+			"[Left/Right] Switch side  [Enter] Take out / Put in  [Esc] Close",
+			TEXT_PADDING.x, screen_height() - TEXT_PADDING.y, T_SIZE / 2., LIGHTGRAY
+		);
+	}
+}
@@ -3,84 +3,179 @@ use macroquad::prelude::*;
 use hot_assets::*;
 
 use crate::primitives::*;
-use crate::level::*;
+use crate::generation::*;
 use crate::types::*;
 use crate::structures::*;
 use crate::menus::*;
+use crate::items::*;
+use crate::scheduler::*;
+use crate::entities::*;
+use crate::combat::*;
+use crate::a_star::*;
 
 enum ControlMode {
 	Build,
 	Move,
+	Deconstruct,
 }
 
 pub struct Player {
 	pub id: usize,
-	end_turn: bool,
+	energy: Energy,
 	pub rect: Rect,
 
 	mining_speed: f32,
 
-	assets: *const AssetManager,
-	tex: *const Texture2D,
+	tex: &'static str,
 
 	control_mode: ControlMode,
 	pub blueprint: Option<Blueprint>,
 	pub selected_blueprint: BlueprintType,
 
 	pub menu: Option<Menu>,
+	pub inventory_menu: Option<InventoryMenu>,
+	pub structure_menu: Option<StructureMenu>,
+
+	pub inventory: Inventory,
 
 	hp: i32,
 	dead: bool,
 }
 
 impl Player {
-	pub fn new(pos: Vec2, assets: &AssetManager) -> Self {
+	pub const CARRY_CAPACITY: f32 = 100.;
+	pub const MAX_HP: i32 = 20;
+	// Tiles the dwarf can see out to
+	pub const SIGHT_RANGE: i32 = 12;
+	pub const STATS: Stats = Stats {
+		accuracy: 0.8,
+		min_damage: 2,
+		max_damage: 4,
+		armor: 1,
+	};
+	pub const SPEED: i32 = NORMAL_SPEED;
+
+	pub fn new(pos: Vec2) -> Self {
 		Self {
 			id: 0,
-			end_turn: false,
+			energy: Energy::new(Self::SPEED),
 
 			rect: Rect::new(pos.x, pos.y, T_SIZE, T_SIZE),
 			mining_speed: 1.,
 			
-			assets: assets as *const AssetManager,
-			tex: &assets.images["dwarf"] as *const Texture2D,
+			tex: "dwarf",
 
 			control_mode: ControlMode::Move,
 			selected_blueprint: BlueprintType::DEFAULT,
 			blueprint: None,
 
 			menu: None,
+			inventory_menu: None,
+			structure_menu: None,
+
+			inventory: Inventory::new(Self::CARRY_CAPACITY),
 
-			hp: 10,
+			hp: Self::MAX_HP,
 			dead: false,
 		}
 	}
 
-	fn move_to(&mut self, direction: Vec2, world: &mut Chunk, entities: *mut Vec<Box<dyn Entity>>) -> bool {
+	// Bumping into something solid or friendly doesn't use up the turn, bumping into an enemy attacks it
+	fn move_to(&mut self, direction: Vec2, world: &mut Level, entities: &mut Entities) -> Option<Action> {
 		let d_pos = self.rect.point() + direction;
-		let check_wall = Wall::new(d_pos, WallData::Basic);
+		let check_rect = Rect::new(d_pos.x, d_pos.y, T_SIZE, T_SIZE);
 
-		if let Some(i) = world.colliders.iter().position(|w: &Wall| w.rect == check_wall.rect) {
-			world.damage_terrain(i, self.mining_speed);
-			return true;
+		if direction == Vec2::ZERO {
+			return Some(Action::Wait);
 		}
 
-		for structure in &mut world.structures {
-			if structure.rect().contains(check_wall.rect.center()) {
-				if structure.collides() {
-					return true;
-				}
+		if !world.is_loaded(d_pos) {
+			return None;
+		}
+
+		if world.is_wall_at(d_pos) {
+			world.damage_terrain(d_pos, self.mining_speed);
+			return Some(Action::Mine);
+		}
+
+		for structure in world.structures_mut() {
+			if structure.rect().contains(check_rect.center()) && structure.collides() {
+				return None;
 			}
 		}
 
-		unsafe {
-			for entity in &mut *entities {
+		if let Some(other) = entities.at(check_rect.center()).and_then(|id| entities.get_mut(id)) {
+			// Same as for monsters, no swinging diagonally past a blocked corner
+			if hostile(Faction::Dwarves, other.faction()) && within_reach(world, self.rect.point(), d_pos) {
+				attack(Self::STATS, other, &mut world.rng);
+				return Some(Action::Attack);
 			}
+
+			return None;
 		}
 
 		self.rect.move_to(d_pos);
 
-		false
+		Some(Action::Move)
+	}
+
+	fn pick_up(&mut self, world: &mut Level) -> bool {
+		let picked_up = world.take_items_at(self.rect.point());
+
+		if picked_up.is_empty() {
+			return false;
+		}
+
+		let mut took_any = false;
+
+		for stack in picked_up {
+			if let Some(rest) = self.inventory.add(stack) {
+				took_any |= rest.amount < stack.amount;
+				world.drop_item(self.rect.point(), rest);
+			} else {
+				took_any = true;
+			}
+		}
+
+		took_any
+	}
+
+	// Any structure touching the tiles around the player
+	fn adjacent_structure(&self, world: &Level) -> Option<Vec2> {
+		let reach = Rect::new(self.rect.x - T_SIZE / 2., self.rect.y - T_SIZE / 2., T_SIZE * 2., T_SIZE * 2.);
+
+		world.structures()
+			.find(|s| s.rect().overlaps(&reach))
+			.map(|s| s.rect().center())
+	}
+
+	fn transfer(&mut self, structure: &mut Box<dyn Structure>, slot: usize, stack: Option<ItemStack>) {
+		let Some(slot) = structure.slots_mut().get_mut(slot) else {
+			return;
+		};
+
+		match stack {
+			Some(stack) => {
+				let amount = stack.amount.min(slot.space_for(stack.item));
+
+				if let Some(taken) = self.inventory.take(stack.item, amount) {
+					slot.insert(taken);
+				}
+			}
+			None => {
+				if let Some(stack) = slot.take(i32::MAX) {
+					if let Some(rest) = self.inventory.add(stack) {
+						slot.insert(rest);
+					}
+				}
+			}
+		}
+	}
+
+	fn drop_stack(&mut self, stack: ItemStack, world: &mut Level) {
+		if let Some(dropped) = self.inventory.take(stack.item, stack.amount) {
+			world.drop_item(self.rect.point(), dropped);
+		}
 	}
 }
 
@@ -98,13 +193,20 @@ const DIRECTION_CONTROLS: [Vec2; 9] = [
 	vec2(T_SIZE, -T_SIZE),
 ];
 
-impl Entity for Player {
-    fn update(&mut self, _entities: *mut Vec<Box<dyn Entity>>, _world: &mut Chunk) {
-    }
-
-    fn draw(&mut self, entities: *mut Vec<Box<dyn Entity>>, world: &mut Chunk) {
-    	self.end_turn = false;
+impl Player {
+	fn direction_pressed() -> Option<Vec2> {
+		let keys = [
+			KeyCode::Kp1, KeyCode::Kp2, KeyCode::Kp3,
+			KeyCode::Kp4, KeyCode::Kp5, KeyCode::Kp6,
+			KeyCode::Kp7, KeyCode::Kp8, KeyCode::Kp9,
+		];
+
+		keys.into_iter().position(is_key_pressed).map(|i| DIRECTION_CONTROLS[i])
+	}
+}
 
+impl Entity for Player {
+    fn input(&mut self, world: &Level) -> Option<Command> {
     	match self.control_mode {
     		ControlMode::Build => {
     			if is_key_pressed(KeyCode::Escape) {
@@ -113,111 +215,285 @@ impl Entity for Player {
     			}
 
     			if let Some(ref mut blueprint) = &mut self.blueprint {
-    				blueprint.draw(world);
-
-    				if is_key_pressed(KeyCode::Kp1) {
-			    		blueprint.move_toward(DIRECTION_CONTROLS[0], world);
-					} else if is_key_pressed(KeyCode::Kp2) {
-			    		blueprint.move_toward(DIRECTION_CONTROLS[1], world);
-					} else if is_key_pressed(KeyCode::Kp3) {
-			    		blueprint.move_toward(DIRECTION_CONTROLS[2], world);
-					} else if is_key_pressed(KeyCode::Kp4) {
-			    		blueprint.move_toward(DIRECTION_CONTROLS[3], world);
-					} else if is_key_pressed(KeyCode::Kp6) {
-			    		blueprint.move_toward(DIRECTION_CONTROLS[5], world);
-					} else if is_key_pressed(KeyCode::Kp7) {
-			    		blueprint.move_toward(DIRECTION_CONTROLS[6], world);
-					} else if is_key_pressed(KeyCode::Kp8) {
-			    		blueprint.move_toward(DIRECTION_CONTROLS[7], world);
-					} else if is_key_pressed(KeyCode::Kp9) {
-			    		blueprint.move_toward(DIRECTION_CONTROLS[8], world);
-					}
+    				if let Some(direction) = Self::direction_pressed() {
+    					blueprint.move_toward(direction, world, &self.inventory);
+    				}
 
 					if is_key_pressed(KeyCode::Tab) {
 						self.menu = Some(Menu {});
 					}
 
 					if is_key_pressed(KeyCode::Q) {
-						blueprint.rotate_left(world);
+						blueprint.rotate_left(world, &self.inventory);
 					} else if is_key_pressed(KeyCode::E) {
-						blueprint.rotate_right(world);
+						blueprint.rotate_right(world, &self.inventory);
 					}
 
 					if is_key_pressed(KeyCode::Enter) {
-						blueprint.update_valid(world);
+						return Some(Command::Build);
+					}
+    			}
+    		}
+    		ControlMode::Deconstruct => {
+    			if is_key_pressed(KeyCode::Escape) {
+    				self.control_mode = ControlMode::Move;
+    			}
 
-						if blueprint.valid {
-							blueprint.place(world);
-						}
+    			if let Some(direction) = Self::direction_pressed() {
+    				self.control_mode = ControlMode::Move;
 
-						blueprint.update_valid(world);
-					}
+    				if direction != Vec2::ZERO {
+    					return Some(Command::Deconstruct(direction));
+    				}
     			}
     		}
     		ControlMode::Move => {
+    			if is_key_pressed(KeyCode::X) {
+    				self.control_mode = ControlMode::Deconstruct;
+    			}
+
     			if is_key_pressed(KeyCode::B) {
     				self.control_mode = ControlMode::Build;
-    				unsafe {
-    					self.blueprint = Some(
-	    					Blueprint::new(
-	    						vec2(self.rect.x + T_SIZE, self.rect.y),
-	    						&*self.assets,
-	    						self.selected_blueprint,
-	    					)
-	    				);
+    				self.blueprint = Some(
+    					Blueprint::new(
+    						vec2(self.rect.x + T_SIZE, self.rect.y),
+    						self.selected_blueprint,
+    					)
+    				);
+
+    				if let Some(ref mut blueprint) = self.blueprint {
+    					blueprint.update_valid(world, &self.inventory);
     				}
     			}
 
-    			if is_key_pressed(KeyCode::Kp1) {
-		    		self.move_to(DIRECTION_CONTROLS[0], world, entities);
-		    		self.end_turn = true;
-				} else if is_key_pressed(KeyCode::Kp2) {
-		    		self.move_to(DIRECTION_CONTROLS[1], world, entities);
-		    		self.end_turn = true;
-				} else if is_key_pressed(KeyCode::Kp3) {
-		    		self.move_to(DIRECTION_CONTROLS[2], world, entities);
-		    		self.end_turn = true;
-				} else if is_key_pressed(KeyCode::Kp4) {
-		    		self.move_to(DIRECTION_CONTROLS[3], world, entities);
-		    		self.end_turn = true;
-				} else if is_key_pressed(KeyCode::Kp5) {
-		    		self.move_to(DIRECTION_CONTROLS[4], world, entities);
-		    		self.end_turn = true;
-				} else if is_key_pressed(KeyCode::Kp6) {
-		    		self.move_to(DIRECTION_CONTROLS[5], world, entities);
-		    		self.end_turn = true;
-				} else if is_key_pressed(KeyCode::Kp7) {
-		    		self.move_to(DIRECTION_CONTROLS[6], world, entities);
-		    		self.end_turn = true;
-				} else if is_key_pressed(KeyCode::Kp8) {
-		    		self.move_to(DIRECTION_CONTROLS[7], world, entities);
-		    		self.end_turn = true;
-				} else if is_key_pressed(KeyCode::Kp9) {
-		    		self.move_to(DIRECTION_CONTROLS[8], world, entities);
-		    		self.end_turn = true;
+				if is_key_pressed(KeyCode::I) {
+					self.inventory_menu = Some(InventoryMenu {});
 				}
+
+				if is_key_pressed(KeyCode::F) {
+					if let Some(pos) = self.adjacent_structure(world) {
+						self.structure_menu = Some(StructureMenu::new(pos));
+					}
+				}
+
+				if is_key_pressed(KeyCode::G) {
+					return Some(Command::PickUp);
+				}
+
+    			if let Some(direction) = Self::direction_pressed() {
+    				return Some(Command::Step(direction));
+    			}
+    		}
+    	}
+
+    	None
+    }
+
+    fn act(&mut self, command: Command, entities: &mut Entities, world: &mut Level) -> Option<Action> {
+    	match command {
+    		Command::Step(direction) => self.move_to(direction, world, entities),
+    		Command::PickUp => self.pick_up(world).then_some(Action::PickUp),
+    		Command::Drop(stack) => {
+    			self.drop_stack(stack, world);
+    			None
+    		}
+    		Command::Build => {
+    			let blueprint = self.blueprint.as_mut()?;
+    			blueprint.update_valid(world, &self.inventory);
+
+    			let placed = blueprint.valid;
+    			if placed {
+    				blueprint.place(world, &mut self.inventory);
+    			}
+
+    			blueprint.update_valid(world, &self.inventory);
+    			placed.then_some(Action::Build)
+    		}
+    		Command::Deconstruct(direction) => {
+    			world.deconstruct(self.rect.center() + direction).then_some(Action::Deconstruct)
+    		}
+    		Command::Transfer { pos, slot, stack } => {
+    			if let Some(structure) = world.structure_at_mut(pos) {
+    				self.transfer(structure, slot, stack);
+    			}
+
+    			None
     		}
     	}
+    }
 
-    	unsafe {
-    		draw_texture_ex(
-	    		&*self.tex as &Texture2D, self.rect.x, self.rect.y, WHITE,
-	    		DrawTextureParams {
-	    			..Default::default()
-	    		}
-	    	);
+    // The player only acts through `act`, the scheduler never calls this
+    fn update(&mut self, _entities: &mut Entities, _world: &mut Level) -> Action {
+    	Action::Wait
+    }
+
+    fn draw(&self, world: &Level, assets: &AssetManager) {
+    	match self.control_mode {
+    		ControlMode::Build => {
+    			if let Some(blueprint) = &self.blueprint {
+    				blueprint.draw(world, assets);
+    			}
+    		}
+    		ControlMode::Deconstruct => {
+    			draw_text("Deconstruct which way?", self.rect.x, self.rect.y - T_SIZE / 4., T_SIZE / 2., RED);
+    		}
+    		ControlMode::Move => {}
     	}
+
+    	draw_texture_ex(
+    		&assets.images[self.tex], self.rect.x, self.rect.y, WHITE,
+    		DrawTextureParams {
+    			..Default::default()
+    		}
+    	);
+
+    	draw_health_bar(self.rect, self.hp, Self::MAX_HP);
     }
 
-    fn data(&mut self) -> EntityData {
+    fn data(&self) -> EntityData {
     	EntityData::Player {
     		rect: self.rect,
-    		end_turn: self.end_turn,
-    		obj: self as *mut Self,
     	}
     }
 
+    fn rect(&self) -> Rect {
+    	self.rect
+    }
+
+    fn stats(&self) -> Stats {
+    	Self::STATS
+    }
+
+    fn faction(&self) -> Faction {
+    	Faction::Dwarves
+    }
+
+    fn corpse(&self) -> Option<&'static str> {
+    	Some(self.tex)
+    }
+
+    fn drops(&mut self) -> Vec<ItemStack> {
+    	std::mem::take(&mut self.inventory.stacks)
+    }
+
+    fn energy(&self) -> &Energy {
+    	&self.energy
+    }
+
+    fn energy_mut(&mut self) -> &mut Energy {
+    	&mut self.energy
+    }
+
     fn id(&self) -> usize {
     	self.id
     }
-}
\ No newline at end of file
+
+    fn set_id(&mut self, id: usize) {
+    	self.id = id;
+    }
+
+    fn as_player_mut(&mut self) -> Option<&mut Player> {
+    	Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::kobold::*;
+	use crate::test_utils::*;
+
+	#[test]
+	fn stepping_into_rock_mines_it() {
+		let mut level = Level::new(1234);
+		let mut entities = Entities::new();
+		let mut player = Player::new(tile(1, 1));
+
+		clear(&mut level, 0, 1, 3, 1);
+		put_wall(&mut level, 2, 1);
+
+		let action = player.act(Command::Step(vec2(T_SIZE, 0.)), &mut entities, &mut level);
+		assert_eq!(action, Some(Action::Mine));
+		assert_eq!(player.rect.point(), tile(1, 1));
+
+		player.act(Command::Step(vec2(T_SIZE, 0.)), &mut entities, &mut level);
+		assert!(level.is_wall_at(tile(2, 1)));
+
+		player.act(Command::Step(vec2(T_SIZE, 0.)), &mut entities, &mut level);
+		assert!(!level.is_wall_at(tile(2, 1)));
+
+		let action = player.act(Command::Step(vec2(T_SIZE, 0.)), &mut entities, &mut level);
+		assert_eq!(action, Some(Action::Move));
+		assert_eq!(player.rect.point(), tile(2, 1));
+
+		assert_eq!(player.act(Command::PickUp, &mut entities, &mut level), Some(Action::PickUp));
+		assert_eq!(player.inventory.count(ItemType::Stone), 1);
+	}
+
+	#[test]
+	fn bumping_into_enemies_attacks_them() {
+		let mut level = Level::new(1234);
+		let mut entities = Entities::new();
+		let mut player = Player::new(tile(1, 1));
+
+		level.damage_terrain(tile(2, 1), f32::MAX);
+		let kobold = entities.spawn(Box::new(Kobold::new(tile(2, 1))));
+		entities.flush();
+
+		let action = player.act(Command::Step(vec2(T_SIZE, 0.)), &mut entities, &mut level);
+		assert_eq!(action, Some(Action::Attack));
+		assert_eq!(player.rect.point(), tile(1, 1));
+
+		for _ in 0..50 {
+			player.act(Command::Step(vec2(T_SIZE, 0.)), &mut entities, &mut level);
+		}
+
+		assert!(entities.get(kobold).unwrap().dead());
+	}
+
+	#[test]
+	fn no_attacking_round_corners() {
+		let mut level = Level::new(1234);
+		let mut entities = Entities::new();
+		let mut player = Player::new(tile(1, 1));
+
+		clear(&mut level, 1, 1, 2, 2);
+		put_wall(&mut level, 2, 1);
+		put_wall(&mut level, 1, 2);
+		let kobold = entities.spawn(Box::new(Kobold::new(tile(2, 2))));
+		entities.flush();
+
+		let action = player.act(Command::Step(vec2(T_SIZE, T_SIZE)), &mut entities, &mut level);
+		assert_eq!(action, None);
+		assert_eq!(entities.get(kobold).unwrap().hp(), Kobold::MAX_HP);
+	}
+
+	#[test]
+	fn transfers_move_items_between_inventory_and_slots() {
+		let mut level = Level::new(1234);
+		let mut entities = Entities::new();
+		let mut player = Player::new(tile(0, 0));
+		player.inventory.add(ItemStack::new(ItemType::Stone, 5));
+
+		let rect = footprint(1, 0, Crusher::SIZE);
+		level.add_structure(Box::new(Crusher::new(rect, Orientation::North)));
+
+		let put_in = Command::Transfer {
+			pos: rect.center(),
+			slot: 0,
+			stack: Some(ItemStack::new(ItemType::Stone, 3)),
+		};
+		assert_eq!(player.act(put_in, &mut entities, &mut level), None);
+		assert_eq!(player.inventory.count(ItemType::Stone), 2);
+		assert_eq!(level.structure_at(rect.center()).unwrap().slots()[0].stack, Some(ItemStack::new(ItemType::Stone, 3)));
+
+		let take_out = Command::Transfer {
+			pos: rect.center(),
+			slot: 0,
+			stack: None,
+		};
+		player.act(take_out, &mut entities, &mut level);
+		assert_eq!(player.inventory.count(ItemType::Stone), 5);
+		assert_eq!(level.structure_at(rect.center()).unwrap().slots()[0].stack, None);
+	}
+}
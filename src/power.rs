@@ -0,0 +1,225 @@
+use macroquad::prelude::*;
+
+use crate::primitives::*;
+use crate::types::*;
+
+pub struct PowerNode {
+	// Watts this node can put into its grid this turn
+	pub output: f32,
+	// Watts this node wants from its grid
+	pub demand: f32,
+	// How much of the demand the grid could cover last turn, 0 to 1
+	pub satisfaction: f32,
+	// How much of the output the grid used last turn, 0 to 1
+	pub load: f32,
+}
+
+impl PowerNode {
+	// Below this much of their demand, machines stall completely
+	pub const MIN_SATISFACTION: f32 = 0.25;
+
+	pub fn producer(output: f32) -> Self {
+		Self {
+			output,
+			demand: 0.,
+			satisfaction: 1.,
+			load: 0.,
+		}
+	}
+
+	pub fn consumer(demand: f32) -> Self {
+		Self {
+			output: 0.,
+			demand,
+			satisfaction: 0.,
+			load: 0.,
+		}
+	}
+
+	// Multiplier for how fast a machine on this node should work
+	pub fn speed(&self) -> f32 {
+		if self.satisfaction < Self::MIN_SATISFACTION {
+			0.
+		} else {
+			self.satisfaction
+		}
+	}
+}
+
+pub struct PowerGrid {
+	pub supply: f32,
+	pub demand: f32,
+	pub bounds: Rect,
+}
+
+impl PowerGrid {
+	pub fn satisfaction(&self) -> f32 {
+		if self.demand <= 0. {
+			// Idle machines only count as powered if there's something to power them
+			if self.supply > 0. {
+				1.
+			} else {
+				0.
+			}
+		} else {
+			(self.supply / self.demand).min(1.)
+		}
+	}
+
+	pub fn load(&self) -> f32 {
+		if self.supply <= 0. {
+			0.
+		} else {
+			(self.demand / self.supply).min(1.)
+		}
+	}
+
+	pub fn draw(&self) {
+		let color = if self.satisfaction() >= 1. {
+			YELLOW
+		} else if self.satisfaction() >= PowerNode::MIN_SATISFACTION {
+			ORANGE
+		} else {
+			RED
+		};
+
+		draw_rectangle_lines(self.bounds.x, self.bounds.y, self.bounds.w, self.bounds.h, 4., color);
+		draw_text(
+			&format!("{:.0}W / {:.0}W", self.supply, self.demand),
+			self.bounds.x, self.bounds.y - T_SIZE / 4., T_SIZE / 2., color
+		);
+	}
+}
+
+fn find_root(parents: &mut [usize], i: usize) -> usize {
+	let mut root = i;
+	while parents[root] != root {
+		root = parents[root];
+	}
+
+	parents[i] = root;
+	root
+}
+
+// Structures with a power node are on the same grid when they touch, directly or through
+// other powered structures. Every node gets its grid's satisfaction and load written back.
+pub fn build_grids(structures: &mut [&mut Box<dyn Structure>]) -> Vec<PowerGrid> {
+	let powered = (0..structures.len())
+		.filter(|&i| structures[i].power().is_some())
+		.collect::<Vec<usize>>();
+
+	let mut parents = (0..structures.len()).collect::<Vec<usize>>();
+
+	for a in 0..powered.len() {
+		let reach = structures[powered[a]].rect();
+		let reach = Rect::new(reach.x - T_SIZE / 2., reach.y - T_SIZE / 2., reach.w + T_SIZE, reach.h + T_SIZE);
+
+		for b in (a + 1)..powered.len() {
+			if reach.overlaps(&structures[powered[b]].rect()) {
+				let root_a = find_root(&mut parents, powered[a]);
+				let root_b = find_root(&mut parents, powered[b]);
+				parents[root_a] = root_b;
+			}
+		}
+	}
+
+	let mut roots = Vec::<usize>::new();
+	let mut grids = Vec::<PowerGrid>::new();
+	let mut grid_of = vec![0; structures.len()];
+
+	for &i in &powered {
+		let root = find_root(&mut parents, i);
+		let rect = structures[i].rect();
+		let node = structures[i].power().unwrap();
+
+		let grid_index = if let Some(g) = roots.iter().position(|&r| r == root) {
+			g
+		} else {
+			roots.push(root);
+			grids.push(PowerGrid {
+				supply: 0.,
+				demand: 0.,
+				bounds: rect,
+			});
+			grids.len() - 1
+		};
+
+		let grid = &mut grids[grid_index];
+		grid.supply += node.output;
+		grid.demand += node.demand;
+		grid.bounds = grid.bounds.combine_with(rect);
+		grid_of[i] = grid_index;
+	}
+
+	for &i in &powered {
+		let grid = &grids[grid_of[i]];
+		let node = structures[i].power_mut().unwrap();
+		node.satisfaction = grid.satisfaction();
+		node.load = grid.load();
+	}
+
+	grids
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::structures::*;
+	use crate::test_utils::*;
+
+	fn generator(x: i32, y: i32) -> Box<dyn Structure> {
+		let mut generator = Box::new(DieselGenerator::new(footprint(x, y, DieselGenerator::SIZE), Orientation::North)) as Box<dyn Structure>;
+		generator.power_mut().unwrap().output = DieselGenerator::OUTPUT;
+		generator
+	}
+
+	fn furnace(x: i32, y: i32) -> Box<dyn Structure> {
+		Box::new(ArcFurnace::new(footprint(x, y, ArcFurnace::SIZE), Orientation::North))
+	}
+
+	fn grids_of(structures: &mut [Box<dyn Structure>]) -> Vec<PowerGrid> {
+		build_grids(&mut structures.iter_mut().collect::<Vec<&mut Box<dyn Structure>>>())
+	}
+
+	#[test]
+	fn touching_structures_share_a_grid() {
+		let mut structures = vec![generator(0, 0), generator(5, 0), furnace(10, 0)];
+		let grids = grids_of(&mut structures);
+
+		assert_eq!(grids.len(), 1);
+		assert_eq!(grids[0].supply, DieselGenerator::OUTPUT * 2.);
+		assert_eq!(grids[0].demand, ArcFurnace::DEMAND);
+
+		assert_eq!(structures[2].power().unwrap().satisfaction, 1.);
+		assert_eq!(structures[0].power().unwrap().load, ArcFurnace::DEMAND / (DieselGenerator::OUTPUT * 2.));
+	}
+
+	#[test]
+	fn separate_structures_get_separate_grids() {
+		let mut structures = vec![generator(0, 0), furnace(5, 0), furnace(20, 0)];
+		let grids = grids_of(&mut structures);
+
+		assert_eq!(grids.len(), 2);
+		assert_eq!(structures[1].power().unwrap().satisfaction, 1.);
+		assert_eq!(structures[2].power().unwrap().satisfaction, 0.);
+		assert_eq!(structures[2].power().unwrap().speed(), 0.);
+	}
+
+	#[test]
+	fn underpowered_machines_slow_down_then_stall() {
+		let mut structures = vec![generator(0, 0), furnace(5, 0), furnace(7, 0)];
+		grids_of(&mut structures);
+
+		let expected = DieselGenerator::OUTPUT / (ArcFurnace::DEMAND * 2.);
+		assert_eq!(structures[0].power().unwrap().load, 1.);
+		assert_eq!(structures[1].power().unwrap().speed(), expected);
+
+		for x in 0..4 {
+			structures.push(furnace(9 + x * 2, 0));
+		}
+		grids_of(&mut structures);
+
+		assert!(structures[1].power().unwrap().satisfaction < PowerNode::MIN_SATISFACTION);
+		assert_eq!(structures[1].power().unwrap().speed(), 0.);
+	}
+}
@@ -1,8 +1,15 @@
 use std::f32::consts::PI;
 use macroquad::prelude::*;
 
-use crate::level::*;
+use hot_assets::*;
+
+use crate::generation::*;
 use crate::player::*;
+use crate::scheduler::*;
+use crate::entities::*;
+use crate::items::*;
+use crate::types::*;
+use crate::combat::*;
 
 pub const T_SIZE: f32 = 48.;
 pub const PI_H: f32 = PI / 2.;
@@ -17,6 +24,109 @@ macro_rules! deref {
 	}
 }
 
+// Which way the top of a sprite faces. Rotating goes clockwise on screen, since y points down.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Orientation {
+	North,
+	East,
+	South,
+	West,
+}
+
+impl Orientation {
+	pub fn rotate_right(&self) -> Self {
+		match self {
+			Self::North => Self::East,
+			Self::East => Self::South,
+			Self::South => Self::West,
+			Self::West => Self::North,
+		}
+	}
+
+	pub fn rotate_left(&self) -> Self {
+		match self {
+			Self::North => Self::West,
+			Self::East => Self::North,
+			Self::South => Self::East,
+			Self::West => Self::South,
+		}
+	}
+
+	pub fn radians(&self) -> f32 {
+		match self {
+			Self::North => 0.,
+			Self::East => PI_H,
+			Self::South => PI,
+			Self::West => PI + PI_H,
+		}
+	}
+
+	pub fn swaps_axes(&self) -> bool {
+		matches!(self, Self::East | Self::West)
+	}
+
+	// Size of a footprint once rotated
+	pub fn rotate_size(&self, size: Vec2) -> Vec2 {
+		if self.swaps_axes() {
+			vec2(size.y, size.x)
+		} else {
+			size
+		}
+	}
+
+	// Turns a direction given for the unrotated sprite
+	pub fn rotate_dir(&self, dir: IVec2) -> IVec2 {
+		match self {
+			Self::North => dir,
+			Self::East => ivec2(-dir.y, dir.x),
+			Self::South => -dir,
+			Self::West => ivec2(dir.y, -dir.x),
+		}
+	}
+
+	// Moves a tile of an unrotated `size` footprint (both in tiles) to where it ends up after rotating
+	pub fn rotate_tile(&self, tile: IVec2, size: IVec2) -> IVec2 {
+		match self {
+			Self::North => tile,
+			Self::East => ivec2(size.y - 1 - tile.y, tile.x),
+			Self::South => ivec2(size.x - 1 - tile.x, size.y - 1 - tile.y),
+			Self::West => ivec2(tile.y, size.x - 1 - tile.x),
+		}
+	}
+}
+
+// Draws a sprite so that after rotating around its centre it exactly covers `rect`
+pub fn draw_rotated(tex: &Texture2D, rect: Rect, orientation: Orientation, color: Color) {
+	let size = orientation.rotate_size(rect.size());
+	let pos = rect.center() - size / 2.;
+
+	draw_texture_ex(
+		tex,
+		pos.x, pos.y,
+		color,
+		DrawTextureParams {
+			dest_size: Some(size),
+			rotation: orientation.radians(),
+			..Default::default()
+		}
+	);
+}
+
+pub fn snap_to_tile(pos: Vec2) -> Vec2 {
+	(pos / T_SIZE).floor() * T_SIZE
+}
+
+// Only shown once something has been hurt
+pub fn draw_health_bar(rect: Rect, hp: i32, max_hp: i32) {
+	if hp >= max_hp {
+		return;
+	}
+
+	let fill = (hp as f32 / max_hp as f32).clamp(0., 1.);
+	draw_rectangle(rect.x, rect.y - 6., rect.w, 4., DARKGRAY);
+	draw_rectangle(rect.x, rect.y - 6., rect.w * fill, 4., RED);
+}
+
 pub fn adj_8_t() -> Vec<Vec2> {
 	vec![
 		vec2(0., -T_SIZE),
@@ -33,18 +143,63 @@ pub fn adj_8_t() -> Vec<Vec2> {
 pub enum EntityData {
 	Player {
 		rect: Rect,
-		end_turn: bool,
-		obj: *mut Player,
 	},
 	Kobold {
 		rect: Rect,
 	},
 }
 
-pub trait Entity {
-    fn update(&mut self, _entities: *mut Vec<Box<dyn Entity>>, _world: &mut Chunk);
-    fn draw(&mut self, _entities: *mut Vec<Box<dyn Entity>>, _world: &mut Chunk);
+// Something the player asked for this frame. Turning it into an action is up to `Entity::act`.
+#[derive(Copy, Clone, Debug)]
+pub enum Command {
+	Step(Vec2),
+	PickUp,
+	Drop(ItemStack),
+	Build,
+	Deconstruct(Vec2),
+	// Between the inventory and slot `slot` of the structure at `pos`. A stack goes into the
+	// slot, and without one the slot is emptied into the inventory.
+	Transfer {
+		pos: Vec2,
+		slot: usize,
+		stack: Option<ItemStack>,
+	},
+}
+
+// Each frame goes input, then simulation, then rendering. Only input reads the keyboard and
+// only the simulation touches the world, so it can all run without a window.
+pub trait Entity: Damageable {
+    fn input(&mut self, _world: &Level) -> Option<Command> {
+        None
+    }
 
-    fn data(&mut self) -> EntityData;
+    // Carries out a command, handing back what it cost. Nothing happening costs nothing.
+    fn act(&mut self, _command: Command, _entities: &mut Entities, _world: &mut Level) -> Option<Action> {
+        None
+    }
+
+    // Called by the scheduler whenever the entity has the energy to act
+    fn update(&mut self, _entities: &mut Entities, _world: &mut Level) -> Action;
+    fn draw(&self, _world: &Level, _assets: &AssetManager);
+
+    fn data(&self) -> EntityData;
+    fn rect(&self) -> Rect;
+    fn stats(&self) -> Stats;
+    fn faction(&self) -> Faction;
+    // Sprite left lying on the floor when this dies
+    fn corpse(&self) -> Option<&'static str>;
+
+    // Whatever spills out when this dies
+    fn drops(&mut self) -> Vec<ItemStack> {
+        Vec::new()
+    }
+
+    fn energy(&self) -> &Energy;
+    fn energy_mut(&mut self) -> &mut Energy;
     fn id(&self) -> usize;
+    fn set_id(&mut self, id: usize);
+
+    fn as_player_mut(&mut self) -> Option<&mut Player> {
+        None
+    }
 }
\ No newline at end of file
@@ -0,0 +1,104 @@
+use crate::items::*;
+
+pub struct Recipe {
+	pub inputs: &'static [(ItemType, i32)],
+	pub output: (ItemType, i32),
+	// Turns to finish at full power
+	pub turns: f32,
+	// Watts drawn while it's being worked on
+	pub power: f32,
+}
+
+impl Recipe {
+	// Everything it needs is spread across the machine's input slots
+	pub fn has_inputs(&self, slots: &[Slot]) -> bool {
+		self.inputs.iter().all(|&(item, amount)| {
+			slots.iter()
+				.filter(|s| s.kind == SlotKind::Input)
+				.filter_map(|s| s.stack)
+				.filter(|s| s.item == item)
+				.map(|s| s.amount)
+				.sum::<i32>() >= amount
+		})
+	}
+
+	pub fn consume_inputs(&self, slots: &mut [Slot]) {
+		for &(item, amount) in self.inputs {
+			let mut left = amount;
+
+			for slot in slots.iter_mut().filter(|s| s.kind == SlotKind::Input) {
+				if left > 0 && slot.stack.map(|s| s.item == item).unwrap_or(false) {
+					if let Some(taken) = slot.take(left) {
+						left -= taken.amount;
+					}
+				}
+			}
+		}
+	}
+
+	pub fn output_stack(&self) -> ItemStack {
+		ItemStack::new(self.output.0, self.output.1)
+	}
+}
+
+// First recipe that can actually run with what's in the slots and where the output has room to go
+pub fn find_recipe(recipes: &'static [Recipe], slots: &[Slot], output_slot: usize) -> Option<&'static Recipe> {
+	recipes.iter().find(|r| {
+		r.has_inputs(slots) && slots[output_slot].space_for(r.output.0) >= r.output.1
+	})
+}
+
+pub fn is_ingredient(recipes: &'static [Recipe], item: ItemType) -> bool {
+	recipes.iter().any(|r| r.inputs.iter().any(|&(i, _)| i == item))
+}
+
+pub const SMELTING: &[Recipe] = &[
+	Recipe {
+		inputs: &[(ItemType::ShroomPulp, 3)],
+		output: (ItemType::Diesel, 1),
+		turns: 6.,
+		power: 40.,
+	},
+	Recipe {
+		inputs: &[(ItemType::IronIngot, 2), (ItemType::Gravel, 1)],
+		output: (ItemType::SteelPlate, 2),
+		turns: 8.,
+		power: 100.,
+	},
+	Recipe {
+		inputs: &[(ItemType::CrushedIronOre, 2)],
+		output: (ItemType::IronIngot, 1),
+		turns: 5.,
+		power: 60.,
+	},
+	Recipe {
+		inputs: &[(ItemType::CrushedGoldOre, 2)],
+		output: (ItemType::GoldIngot, 1),
+		turns: 5.,
+		power: 50.,
+	},
+	Recipe {
+		inputs: &[(ItemType::CrushedTesiumOre, 2)],
+		output: (ItemType::TesiumIngot, 1),
+		turns: 6.,
+		power: 70.,
+	},
+	Recipe {
+		inputs: &[(ItemType::CrushedDeotiumOre, 3)],
+		output: (ItemType::DeotiumIngot, 1),
+		turns: 8.,
+		power: 90.,
+	},
+	Recipe {
+		inputs: &[(ItemType::CrushedEdyagiteOre, 3)],
+		output: (ItemType::EdyagiteIngot, 1),
+		turns: 8.,
+		power: 100.,
+	},
+	Recipe {
+		inputs: &[(ItemType::CrushedLashaliteOre, 3)],
+		output: (ItemType::LashaliteIngot, 1),
+		turns: 10.,
+		power: 120.,
+	},
+];
@@ -0,0 +1,297 @@
+use crate::generation::*;
+use crate::entities::*;
+use crate::spawning::*;
+
+// Energy a normal entity gains every clock step, so a standard action comes round once a turn
+pub const NORMAL_SPEED: i32 = 10;
+// Energy an entity needs before it may act, and what a standard action costs
+pub const ACTION_COST: i32 = 100;
+// Clock steps between structure ticks
+pub const STEPS_PER_TURN: u64 = (ACTION_COST / NORMAL_SPEED) as u64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Action {
+	Move,
+	Mine,
+	Attack,
+	Build,
+	Deconstruct,
+	PickUp,
+	Wait,
+}
+
+impl Action {
+	pub fn cost(&self) -> i32 {
+		match self {
+			Self::Move => ACTION_COST,
+			Self::Mine => ACTION_COST * 3 / 2,
+			Self::Attack => ACTION_COST,
+			Self::Build => ACTION_COST * 2,
+			Self::Deconstruct => ACTION_COST * 3 / 2,
+			Self::PickUp => ACTION_COST / 2,
+			Self::Wait => ACTION_COST,
+		}
+	}
+}
+
+pub struct Energy {
+	pub speed: i32,
+	pub amount: i32,
+}
+
+impl Energy {
+	// Starts ready, so new entities get to act straight away
+	pub fn new(speed: i32) -> Self {
+		Self {
+			speed,
+			amount: ACTION_COST,
+		}
+	}
+
+	pub fn ready(&self) -> bool {
+		self.amount >= ACTION_COST
+	}
+
+	pub fn gain(&mut self) {
+		self.amount += self.speed;
+	}
+
+	pub fn spend(&mut self, action: Action) {
+		self.amount -= action.cost();
+	}
+}
+
+pub struct Scheduler {
+	// Clock steps since the level was made
+	pub time: u64,
+}
+
+impl Scheduler {
+	pub fn new() -> Self {
+		Self {
+			time: 0,
+		}
+	}
+
+	pub fn turn(&self) -> u64 {
+		self.time / STEPS_PER_TURN
+	}
+
+	// Runs the clock after the player has spent their energy. Everyone else acts as soon as
+	// they have enough energy, most first, and structures tick once a turn. Stops when it's
+	// the player's move again.
+	pub fn advance(&mut self, entities: &mut Entities, world: &mut Level) {
+		let Some(player_id) = entities.player_id() else {
+			return;
+		};
+
+		loop {
+			loop {
+				let mut ready = entities.iter()
+					.filter(|e| e.id() != player_id && e.energy().ready())
+					.map(|e| (e.id(), e.energy().amount))
+					.collect::<Vec<(usize, i32)>>();
+
+				if ready.is_empty() {
+					break;
+				}
+
+				ready.sort_by_key(|&(_, energy)| -energy);
+
+				for (id, _) in ready {
+					// Might have been despawned by someone who acted earlier this round
+					let Some(mut entity) = entities.take(id) else {
+						continue;
+					};
+
+					let action = entity.update(entities, world);
+					entity.energy_mut().spend(action);
+					entities.put_back(entity);
+				}
+
+				entities.reap(world);
+				entities.flush();
+			}
+
+			match entities.get(player_id) {
+				Some(player) if player.energy().ready() => break,
+				Some(_) => {}
+				None => return,
+			}
+
+			for id in entities.ids() {
+				if let Some(entity) = entities.get_mut(id) {
+					entity.energy_mut().gain();
+				}
+			}
+
+			self.time += 1;
+
+			if self.time.is_multiple_of(STEPS_PER_TURN) {
+				world.tick();
+				populate(entities, world);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::Cell;
+	use std::rc::Rc;
+
+	use macroquad::prelude::*;
+	use hot_assets::*;
+
+	use super::*;
+	use crate::primitives::*;
+	use crate::types::*;
+	use crate::combat::*;
+	use crate::player::*;
+	use crate::structures::*;
+	use crate::items::*;
+	use crate::test_utils::*;
+
+	// Does the same thing every time it gets to act and counts how often that was
+	struct Clockwork {
+		id: usize,
+		hp: i32,
+		dead: bool,
+		energy: Energy,
+		action: Action,
+		acted: Rc<Cell<i32>>,
+	}
+
+	impl Clockwork {
+		const MAX_HP: i32 = 1;
+
+		fn new(speed: i32, action: Action) -> (Self, Rc<Cell<i32>>) {
+			let acted = Rc::new(Cell::new(0));
+
+			let clockwork = Self {
+				id: 0,
+				hp: Self::MAX_HP,
+				dead: false,
+				energy: Energy::new(speed),
+				action,
+				acted: acted.clone(),
+			};
+
+			(clockwork, acted)
+		}
+	}
+
+	lazy_derive!(Damageable, Clockwork);
+
+	impl Entity for Clockwork {
+	    fn update(&mut self, _entities: &mut Entities, _world: &mut Level) -> Action {
+	    	self.acted.set(self.acted.get() + 1);
+	    	self.action
+	    }
+
+	    fn draw(&self, _world: &Level, _assets: &AssetManager) {}
+
+	    fn data(&self) -> EntityData {
+	    	EntityData::Kobold {
+	    		rect: self.rect(),
+	    	}
+	    }
+
+	    fn rect(&self) -> Rect {
+	    	Rect::new(0., 0., T_SIZE, T_SIZE)
+	    }
+
+	    fn stats(&self) -> Stats {
+	    	Player::STATS
+	    }
+
+	    fn faction(&self) -> Faction {
+	    	Faction::Kobolds
+	    }
+
+	    fn corpse(&self) -> Option<&'static str> {
+	    	None
+	    }
+
+	    fn energy(&self) -> &Energy {
+	    	&self.energy
+	    }
+
+	    fn energy_mut(&mut self) -> &mut Energy {
+	    	&mut self.energy
+	    }
+
+	    fn id(&self) -> usize {
+	    	self.id
+	    }
+
+	    fn set_id(&mut self, id: usize) {
+	    	self.id = id;
+	    }
+	}
+
+	// Has the player take `turns` standard actions, running the clock after each one
+	fn play(turns: usize, scheduler: &mut Scheduler, entities: &mut Entities, world: &mut Level) {
+		let player = entities.player_id().unwrap();
+
+		for _ in 0..turns {
+			entities.get_mut(player).unwrap().energy_mut().spend(Action::Move);
+			scheduler.advance(entities, world);
+		}
+	}
+
+	fn setup() -> (Scheduler, Entities, Level) {
+		let mut entities = Entities::new();
+		entities.spawn(Box::new(Player::new(tile(20, 20))));
+		entities.flush();
+
+		(Scheduler::new(), entities, Level::new(1234))
+	}
+
+	#[test]
+	fn faster_entities_act_more_often() {
+		let (mut scheduler, mut entities, mut level) = setup();
+		let (fast, fast_acted) = Clockwork::new(NORMAL_SPEED * 2, Action::Move);
+		let (slow, slow_acted) = Clockwork::new(NORMAL_SPEED, Action::Move);
+		entities.spawn(Box::new(fast));
+		entities.spawn(Box::new(slow));
+		entities.flush();
+
+		play(10, &mut scheduler, &mut entities, &mut level);
+
+		// Both start out ready, so that's one each up front
+		assert_eq!(slow_acted.get(), 1 + 10);
+		assert_eq!(fast_acted.get(), 1 + 20);
+	}
+
+	#[test]
+	fn cheaper_actions_come_round_sooner() {
+		let (mut scheduler, mut entities, mut level) = setup();
+		let (quick, quick_acted) = Clockwork::new(NORMAL_SPEED, Action::PickUp);
+		let (slow, slow_acted) = Clockwork::new(NORMAL_SPEED, Action::Build);
+		entities.spawn(Box::new(quick));
+		entities.spawn(Box::new(slow));
+		entities.flush();
+
+		play(10, &mut scheduler, &mut entities, &mut level);
+
+		assert_eq!(quick_acted.get(), 1 + 20);
+		assert_eq!(slow_acted.get(), 1 + 5);
+	}
+
+	#[test]
+	fn structures_tick_once_a_turn() {
+		let (mut scheduler, mut entities, mut level) = setup();
+		let rect = footprint(1, 1, DieselGenerator::SIZE);
+		let mut generator = Box::new(DieselGenerator::new(rect, Orientation::North)) as Box<dyn Structure>;
+		generator.slots_mut()[0].insert(ItemStack::new(ItemType::LatalaShroom, 10));
+		level.add_structure(generator);
+
+		// Every tick feeds one shroom into the tank
+		play(3, &mut scheduler, &mut entities, &mut level);
+
+		assert_eq!(scheduler.time, 3 * STEPS_PER_TURN);
+		assert_eq!(scheduler.turn(), 3);
+		assert_eq!(level.structure_at(rect.center()).unwrap().slots()[0].stack, Some(ItemStack::new(ItemType::LatalaShroom, 7)));
+	}
+}
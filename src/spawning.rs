@@ -0,0 +1,293 @@
+use ::rand::{Rng, rngs::StdRng};
+use macroquad::prelude::*;
+
+use crate::primitives::*;
+use crate::generation::*;
+use crate::level::*;
+use crate::entities::*;
+use crate::kobold::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MonsterType {
+	Kobold,
+}
+
+impl MonsterType {
+	pub fn spawn(&self, pos: Vec2) -> Box<dyn Entity> {
+		match self {
+			Self::Kobold => Box::new(Kobold::new(pos)),
+		}
+	}
+
+	pub fn of(data: &EntityData) -> Option<Self> {
+		match data {
+			EntityData::Kobold {..} => Some(Self::Kobold),
+			_ => None,
+		}
+	}
+}
+
+pub struct SpawnRule {
+	pub monster: MonsterType,
+	// Most of this monster a single chunk can hold, lairs included
+	pub budget: usize,
+
+	// Chunks closer to the start than this never get a lair
+	pub lair_min_depth: i32,
+	// Open tiles a cavern needs before anything will nest in it
+	pub lair_min_cavern: usize,
+	// Chance for each big enough cavern to hold a lair
+	pub lair_chance: f64,
+	pub lair_size: (i32, i32),
+	// Tiles from the middle of the lair the nest spreads over
+	pub lair_radius: i32,
+
+	// Chance every turn that one more wanders in from somewhere the player has never seen
+	pub trickle_chance: f64,
+	// Tiles away from the player a trickle-in has to appear
+	pub trickle_distance: f32,
+}
+
+pub const SPAWN_RULES: &[SpawnRule] = &[
+	SpawnRule {
+		monster: MonsterType::Kobold,
+		budget: 6,
+
+		lair_min_depth: 1,
+		lair_min_cavern: 150,
+		lair_chance: 0.5,
+		lair_size: (2, 4),
+		lair_radius: 3,
+
+		trickle_chance: 0.01,
+		trickle_distance: 20.,
+	},
+];
+
+// Splits the open floor of a chunk into caverns, each a list of local tiles
+pub fn find_caverns(terrain: &[[bool; Chunk::SIZE]; Chunk::SIZE]) -> Vec<Vec<(usize, usize)>> {
+	let mut seen = [[false; Chunk::SIZE]; Chunk::SIZE];
+	let mut caverns = Vec::new();
+
+	for x in 0..Chunk::SIZE {
+		for y in 0..Chunk::SIZE {
+			if terrain[y][x] || seen[y][x] {
+				continue;
+			}
+
+			let mut cavern = Vec::new();
+			let mut stack = vec![(x, y)];
+			seen[y][x] = true;
+
+			while let Some((cx, cy)) = stack.pop() {
+				cavern.push((cx, cy));
+
+				for (ax, ay) in get_adj(cx as i32, cy as i32) {
+					if ax < 0 || ax >= Chunk::SIZE as i32 || ay < 0 || ay >= Chunk::SIZE as i32 {
+						continue;
+					}
+
+					let (ax, ay) = (ax as usize, ay as usize);
+
+					if !terrain[ay][ax] && !seen[ay][ax] {
+						seen[ay][ax] = true;
+						stack.push((ax, ay));
+					}
+				}
+			}
+
+			caverns.push(cavern);
+		}
+	}
+
+	caverns
+}
+
+// Where the lairs of a freshly generated chunk put their monsters, as local tiles
+pub fn place_lairs(terrain: &[[bool; Chunk::SIZE]; Chunk::SIZE], depth: i32, rng: &mut StdRng) -> Vec<(MonsterType, (usize, usize))> {
+	let mut spawns = Vec::new();
+	let caverns = find_caverns(terrain);
+
+	for rule in SPAWN_RULES {
+		if depth < rule.lair_min_depth {
+			continue;
+		}
+
+		for cavern in caverns.iter().filter(|c| c.len() >= rule.lair_min_cavern) {
+			if !rng.gen_bool(rule.lair_chance) {
+				continue;
+			}
+
+			let (lx, ly) = cavern[rng.gen_range(0..cavern.len())];
+			let mut nest = cavern.iter()
+				.filter(|&&(x, y)| {
+					(x as i32 - lx as i32).abs().max((y as i32 - ly as i32).abs()) <= rule.lair_radius
+				})
+				.copied()
+				.collect::<Vec<(usize, usize)>>();
+
+			for _ in 0..rng.gen_range(rule.lair_size.0..=rule.lair_size.1) {
+				if nest.is_empty() {
+					break;
+				}
+
+				let tile = nest.swap_remove(rng.gen_range(0..nest.len()));
+				spawns.push((rule.monster, tile));
+			}
+		}
+	}
+
+	spawns
+}
+
+fn population(entities: &Entities, monster: MonsterType, chunk_pos: I16Vec2) -> usize {
+	entities.iter()
+		.filter(|e| MonsterType::of(&e.data()) == Some(monster))
+		.filter(|e| Level::chunk_pos_at(e.rect().center()) == chunk_pos)
+		.count()
+}
+
+fn can_spawn_at(entities: &Entities, world: &Level, pos: Vec2) -> bool {
+	world.is_loaded(pos)
+		&& !world.is_wall_at(pos)
+		&& world.structure_at(pos + T_SIZE / 2.).is_none()
+		&& entities.at(pos + T_SIZE / 2.).is_none()
+}
+
+// Run once a turn. Brings in whatever lairs newly generated chunks are waiting on, and now
+// and then lets another monster wander in somewhere unexplored and far from the player, as
+// long as the chunk it would end up in has room in its budget.
+pub fn populate(entities: &mut Entities, world: &mut Level) {
+	for (monster, pos) in world.take_lair_spawns() {
+		let Some(rule) = SPAWN_RULES.iter().find(|r| r.monster == monster) else {
+			continue;
+		};
+
+		if population(entities, monster, Level::chunk_pos_at(pos)) < rule.budget && can_spawn_at(entities, world, pos) {
+			entities.spawn(monster.spawn(pos));
+			entities.flush();
+		}
+	}
+
+	let Some(player_pos) = entities.player_id().and_then(|id| entities.get(id)).map(|p| p.rect().point()) else {
+		return;
+	};
+
+	let chunks = world.chunks.keys().copied().collect::<Vec<I16Vec2>>();
+
+	for rule in SPAWN_RULES {
+		if chunks.is_empty() || !world.rng.gen_bool(rule.trickle_chance) {
+			continue;
+		}
+
+		let chunk_pos = chunks[world.rng.gen_range(0..chunks.len())];
+
+		if population(entities, rule.monster, chunk_pos) >= rule.budget {
+			continue;
+		}
+
+		let local = vec2(
+			world.rng.gen_range(0..Chunk::SIZE) as f32,
+			world.rng.gen_range(0..Chunk::SIZE) as f32,
+		);
+		let pos = chunk_pos.as_vec2() * Chunk::PIXEL_SIZE + local * T_SIZE;
+
+		let unexplored = !world.is_seen(pos) && pos.distance(player_pos) >= rule.trickle_distance * T_SIZE;
+
+		if unexplored && can_spawn_at(entities, world, pos) {
+			entities.spawn(rule.monster.spawn(pos));
+			entities.flush();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::player::*;
+
+	fn kobolds(entities: &Entities) -> Vec<Vec2> {
+		entities.iter()
+			.filter(|e| MonsterType::of(&e.data()) == Some(MonsterType::Kobold))
+			.map(|e| e.rect().point())
+			.collect()
+	}
+
+	#[test]
+	fn lairs_are_seeded_and_only_on_open_floor() {
+		let mut level = Level::new(1234);
+		let mut other = Level::new(1234);
+		let mut total = 0;
+
+		assert!(level.chunks[&I16Vec2::ZERO].lairs.is_empty());
+
+		for x in -2..=2 {
+			for y in -2..=2 {
+				let chunk_pos = i16vec2(x, y);
+				level.generate_chunk(chunk_pos);
+				other.generate_chunk(chunk_pos);
+
+				let lairs = &level.chunks[&chunk_pos].lairs;
+				assert_eq!(lairs, &other.chunks[&chunk_pos].lairs);
+
+				for &(_, pos) in lairs {
+					assert_eq!(Level::chunk_pos_at(pos), chunk_pos);
+					assert!(!level.is_wall_at(pos));
+				}
+
+				total += lairs.len();
+			}
+		}
+
+		assert!(total > 0);
+	}
+
+	#[test]
+	fn lairs_respect_the_budget() {
+		let mut level = Level::new(1234);
+		let mut entities = Entities::new();
+		let rule = &SPAWN_RULES[0];
+
+		let open = (0..Chunk::SIZE * Chunk::SIZE)
+			.map(|i| vec2((i % Chunk::SIZE) as f32, (i / Chunk::SIZE) as f32) * T_SIZE)
+			.filter(|&pos| !level.is_wall_at(pos))
+			.take(rule.budget * 2)
+			.collect::<Vec<Vec2>>();
+
+		level.chunks.get_mut(&I16Vec2::ZERO).unwrap().lairs = open.iter().map(|&pos| (rule.monster, pos)).collect();
+		populate(&mut entities, &mut level);
+
+		assert_eq!(kobolds(&entities).len(), rule.budget);
+		assert!(level.take_lair_spawns().is_empty());
+	}
+
+	#[test]
+	fn trickle_ins_keep_their_distance() {
+		let mut level = Level::new(1234);
+		let mut entities = Entities::new();
+		let rule = &SPAWN_RULES[0];
+
+		entities.spawn(Box::new(Player::new(Vec2::ZERO)));
+		entities.flush();
+
+		// Somewhere already explored, far enough away that only having seen it keeps monsters out
+		let chunk = level.chunks.get_mut(&I16Vec2::ZERO).unwrap();
+		for row in chunk.seen.iter_mut() {
+			row[Chunk::SIZE / 2..].fill(true);
+		}
+
+		for _ in 0..3000 {
+			populate(&mut entities, &mut level);
+		}
+
+		let kobolds = kobolds(&entities);
+		assert!(!kobolds.is_empty());
+		assert!(kobolds.len() <= rule.budget);
+
+		for pos in kobolds {
+			assert!(pos.length() >= rule.trickle_distance * T_SIZE);
+			assert!(!level.is_wall_at(pos));
+			assert!(!level.is_seen(pos));
+		}
+	}
+}
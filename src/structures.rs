@@ -2,7 +2,10 @@ use macroquad::prelude::*;
 
 use crate::types::*;
 use crate::primitives::*;
-use crate::level::*;
+use crate::generation::*;
+use crate::items::*;
+use crate::power::*;
+use crate::recipes::*;
 
 use hot_assets::*;
 
@@ -12,45 +15,206 @@ pub enum BlueprintType {
 	Crusher,
 	ArcFurnace,
 	SteelWall,
+	Conveyor,
 }
 
 impl BlueprintType {
 	pub const DEFAULT: Self = Self::DieselGenerator;
+
+	// What comes back out of a structure, rounded down
+	pub fn refund(&self, fraction: f32) -> Vec<ItemStack> {
+		self.cost().into_iter()
+			.map(|s| ItemStack::new(s.item, (s.amount as f32 * fraction).floor() as i32))
+			.filter(|s| s.amount > 0)
+			.collect()
+	}
+
+	pub fn cost(&self) -> Vec<ItemStack> {
+		match self {
+			Self::DieselGenerator => vec![
+				ItemStack::new(ItemType::Stone, 10),
+				ItemStack::new(ItemType::IronOre, 20),
+			],
+			Self::Crusher => vec![
+				ItemStack::new(ItemType::Stone, 20),
+				ItemStack::new(ItemType::IronOre, 10),
+			],
+			Self::ArcFurnace => vec![
+				ItemStack::new(ItemType::Stone, 30),
+				ItemStack::new(ItemType::IronOre, 15),
+			],
+			Self::SteelWall => vec![
+				ItemStack::new(ItemType::SteelPlate, 2),
+			],
+			Self::Conveyor => vec![
+				ItemStack::new(ItemType::IronIngot, 1),
+			],
+		}
+	}
+
+	// How badly raiders want it wrecked. Walls are only broken when they're in the way.
+	pub fn value(&self) -> i32 {
+		match self {
+			Self::DieselGenerator => 3,
+			Self::ArcFurnace => 2,
+			Self::Crusher => 2,
+			Self::Conveyor => 1,
+			Self::SteelWall => 0,
+		}
+	}
+
+	pub fn ports(&self) -> &'static [PortDef] {
+		match self {
+			Self::DieselGenerator => DieselGenerator::PORTS,
+			Self::Crusher => Crusher::PORTS,
+			Self::ArcFurnace => ArcFurnace::PORTS,
+			Self::SteelWall => SteelWall::PORTS,
+			Self::Conveyor => Conveyor::PORTS,
+		}
+	}
+
+	pub fn size(&self) -> Vec2 {
+		match self {
+			Self::DieselGenerator => DieselGenerator::SIZE,
+			Self::Crusher => Crusher::SIZE,
+			Self::ArcFurnace => ArcFurnace::SIZE,
+			Self::SteelWall => SteelWall::SIZE,
+			Self::Conveyor => Conveyor::SIZE,
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PortKind {
+	Input,
+	Output,
+}
+
+// A port as laid out on the unrotated sprite: the footprint tile it sits on and the side it
+// opens towards, both in tiles
+pub struct PortDef {
+	pub tile: IVec2,
+	pub dir: IVec2,
+	pub kind: PortKind,
+}
+
+// A port once the structure is placed. Items leave an output port onto `outside`, and an
+// input port takes items from whatever is on `outside`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Port {
+	pub tile: Vec2,
+	pub outside: Vec2,
+	pub kind: PortKind,
+}
+
+pub fn place_ports(rect: Rect, orientation: Orientation, size: Vec2, defs: &[PortDef]) -> Vec<Port> {
+	let origin = full_footprint(rect).point();
+	let size = (size / T_SIZE).round().as_ivec2();
+
+	defs.iter().map(|def| {
+		let tile = origin + orientation.rotate_tile(def.tile, size).as_vec2() * T_SIZE;
+		let outside = tile + orientation.rotate_dir(def.dir).as_vec2() * T_SIZE;
+
+		Port {
+			tile,
+			outside,
+			kind: def.kind,
+		}
+	}).collect()
+}
+
+pub fn draw_ports(ports: &[Port]) {
+	for port in ports {
+		let color = match port.kind {
+			PortKind::Input => SKYBLUE,
+			PortKind::Output => ORANGE,
+		};
+
+		// A little tab on the edge the port opens through
+		let edge = port.tile + T_SIZE / 2. + (port.outside - port.tile) * 0.4;
+		draw_rectangle(edge.x - T_SIZE / 8., edge.y - T_SIZE / 8., T_SIZE / 4., T_SIZE / 4., color);
+	}
+}
+
+// Fills whichever slots take items from outside, handing back what didn't fit
+pub fn insert_into(structure: &mut Box<dyn Structure>, stack: ItemStack) -> Option<ItemStack> {
+	let mut rest = Some(stack);
+
+	for slot in structure.slots_mut() {
+		if slot.kind == SlotKind::Input || slot.kind == SlotKind::Belt {
+			if let Some(stack) = rest {
+				rest = slot.insert(stack);
+			}
+		}
+	}
+
+	rest
+}
+
+// Placed rects are shrunk by a pixel on every side so neighbours don't count as overlapping,
+// this gets back the tiles they actually cover
+pub fn full_footprint(rect: Rect) -> Rect {
+	Rect::new(rect.x - 1., rect.y - 1., rect.w + 2., rect.h + 2.)
+}
+
+// Cracks spread over the footprint as hp drops. The tiles crack in a fixed scrambled order
+// so existing cracks stay put while new ones appear.
+pub fn draw_damage(rect: Rect, hp: i32, max_hp: i32, assets: &AssetManager) {
+	let rect = full_footprint(rect);
+	let columns = (rect.w / T_SIZE).round().max(1.) as u32;
+	let rows = (rect.h / T_SIZE).round().max(1.) as u32;
+	let damage = (1. - hp as f32 / max_hp as f32).clamp(0., 1.);
+	let cracked = (damage * (columns * rows) as f32).ceil() as usize;
+
+	let mut order = (0..columns * rows).collect::<Vec<u32>>();
+	order.sort_by_key(|t| t.wrapping_mul(2654435761));
+
+	for &tile in order.iter().take(cracked) {
+		draw_texture(
+			&assets.images[&format!("crack.{}", tile % 4)],
+			rect.x + (tile % columns) as f32 * T_SIZE, rect.y + (tile / columns) as f32 * T_SIZE,
+			WHITE
+		);
+	}
 }
 
 pub struct Blueprint {
 	pub rect: Rect,
 	pub valid: bool,
+	pub invalid_reason: Option<String>,
 
-	pub rotation: f32,
+	pub orientation: Orientation,
 
 	blueprint_type: BlueprintType,
 
-	tex: *const Texture2D,
-	assets: *const AssetManager,
+	tex: &'static str,
 }
 
 impl Blueprint {
-	pub fn new(pos: Vec2, assets: &AssetManager, t: BlueprintType) -> Self {
+	pub fn new(pos: Vec2, t: BlueprintType) -> Self {
 		let rect: Rect;
-		let tex: *const Texture2D;
+		let tex: &'static str;
 
 		match t {
 			BlueprintType::DieselGenerator => {
 				rect = Rect::new(pos.x + T_SIZE + 1., pos.y + 1., DieselGenerator::SIZE.x - 2., DieselGenerator::SIZE.y - 2.);
-				tex = &assets.images["diesel_generator"] as *const Texture2D;
+				tex = "diesel_generator";
 			}
 			BlueprintType::Crusher => {
 				rect = Rect::new(pos.x + T_SIZE + 1., pos.y + 1., Crusher::SIZE.x - 2., Crusher::SIZE.y - 2.);
-				tex = &assets.images["crusher"] as *const Texture2D;
+				tex = "crusher";
 			}
 			BlueprintType::ArcFurnace => {
 				rect = Rect::new(pos.x + T_SIZE + 1., pos.y + 1., ArcFurnace::SIZE.x - 2., ArcFurnace::SIZE.y - 2.);
-				tex = &assets.images["arc_furnace"] as *const Texture2D;
+				tex = "arc_furnace";
 			}
 			BlueprintType::SteelWall => {
 				rect = Rect::new(pos.x + T_SIZE + 1., pos.y + 1., SteelWall::SIZE.x - 2., SteelWall::SIZE.y - 2.);
-				tex = &assets.images["steel_plate_wall"] as *const Texture2D;
+				tex = "steel_plate_wall";
+			}
+			BlueprintType::Conveyor => {
+				rect = Rect::new(pos.x + T_SIZE + 1., pos.y + 1., Conveyor::SIZE.x - 2., Conveyor::SIZE.y - 2.);
+				tex = "conveyor";
 			}
 		}
 
@@ -58,86 +222,89 @@ impl Blueprint {
 			// rect: Rect::new(rect.x + 1., rect.y + 1., rect.w - 2., rect.h - 2.),
 			rect,
 			valid: false,
+			invalid_reason: None,
 
-			rotation: 0.,
+			orientation: Orientation::North,
 
 			blueprint_type: t,
 
 			tex,
-			assets: assets as *const AssetManager,
 		}
 	}
 
-	pub fn place(&mut self, world: &mut Chunk) {
-		let structure: Box<dyn Structure>;
+	pub fn place(&mut self, world: &mut Level, inventory: &mut Inventory) {
+		for stack in self.blueprint_type.cost() {
+			inventory.take(stack.item, stack.amount);
+		}
 
-		unsafe {
-			match self.blueprint_type {
-				BlueprintType::DieselGenerator => {
-					structure = Box::new(DieselGenerator::new(self.rect, &*self.assets, self.rotation));
-				}
-				BlueprintType::Crusher => {
-					structure = Box::new(Crusher::new(self.rect, &*self.assets, self.rotation));
-				}
-				BlueprintType::ArcFurnace => {
-					structure = Box::new(ArcFurnace::new(self.rect, &*self.assets, self.rotation));
-				}
-				BlueprintType::SteelWall => {
-					structure = Box::new(SteelWall::new(self.rect, &*self.assets, self.rotation));
-				}
-			}
+		let structure: Box<dyn Structure> = match self.blueprint_type {
+			BlueprintType::DieselGenerator => Box::new(DieselGenerator::new(self.rect, self.orientation)),
+			BlueprintType::Crusher => Box::new(Crusher::new(self.rect, self.orientation)),
+			BlueprintType::ArcFurnace => Box::new(ArcFurnace::new(self.rect, self.orientation)),
+			BlueprintType::SteelWall => Box::new(SteelWall::new(self.rect, self.orientation)),
+			BlueprintType::Conveyor => Box::new(Conveyor::new(self.rect, self.orientation)),
+		};
 
-			world.structures.push(
-				structure
-			);
-		}
+		world.add_structure(
+			structure
+		);
 
-		self.update_valid(world);
+		self.update_valid(world, inventory);
 	}
 
-	pub fn update_valid(&mut self, world: &Chunk) {
+	pub fn update_valid(&mut self, world: &Level, inventory: &Inventory) {
 		self.valid = true;
+		self.invalid_reason = None;
 
 		// let check_rect = Rect::new(self.rect.x + 1., self.rect.y + 1., self.rect.w - 2., self.rect.h - 2.);
 
-		for wall in &world.colliders {
-			if wall.rect.overlaps(&self.rect) {
-				self.valid = false;
-				break;
-			}	
+		if world.walls_overlapping(self.rect).next().is_some() {
+			self.valid = false;
+			self.invalid_reason = Some("Blocked by rock".to_string());
 		}
 
-		for structure in &world.structures {
+		for structure in world.structures() {
 			if structure.rect().overlaps(&self.rect) {
 				self.valid = false;
+				self.invalid_reason = Some("Blocked by a structure".to_string());
 				break;
 			}
 		}
+
+		let missing = self.blueprint_type.cost().into_iter()
+			.filter(|s| !inventory.has(s.item, s.amount))
+			.map(|s| format!("{} {}", s.amount - inventory.count(s.item), s.item.name()))
+			.collect::<Vec<String>>();
+
+		if !missing.is_empty() {
+			self.valid = false;
+			self.invalid_reason = Some(format!("Needs {}", missing.join(", ")));
+		}
 	}
 
-	pub fn move_toward(&mut self, rhs: Vec2, world: &Chunk) {
+	pub fn move_toward(&mut self, rhs: Vec2, world: &Level, inventory: &Inventory) {
 		let new_rect = self.rect.offset(rhs);
 		
 		self.rect = new_rect;
 
-		self.update_valid(world);
+		self.update_valid(world, inventory);
 	}
 
-	pub fn rotate_left(&mut self, world: &Chunk) {
-		self.rotation -= PI_H;
+	pub fn rotate_left(&mut self, world: &Level, inventory: &Inventory) {
+		self.orientation = self.orientation.rotate_left();
 		let new_rect = Rect::new(self.rect.x, self.rect.y, self.rect.h, self.rect.w);
 		self.rect = new_rect;
-		self.update_valid(world);
+		self.update_valid(world, inventory);
 	}
 
-	pub fn rotate_right(&mut self, world: &Chunk) {
-		self.rotation += PI_H;
+	pub fn rotate_right(&mut self, world: &Level, inventory: &Inventory) {
+		self.orientation = self.orientation.rotate_right();
 		let new_rect = Rect::new(self.rect.x, self.rect.y, self.rect.h, self.rect.w);
 		self.rect = new_rect;
-		self.update_valid(world);
+		self.update_valid(world, inventory);
 	}
 
-	pub fn draw(&mut self, _world: &Chunk) {
+	pub fn draw(&self, _world: &Level, assets: &AssetManager) {
 		let color = if self.valid {
 			GREEN
 		} else {
@@ -145,18 +312,13 @@ impl Blueprint {
 		};
 
 
-		unsafe {
-			draw_texture_ex(
-				&*self.tex,
-				self.rect.x, self.rect.y,
-				color,
-				DrawTextureParams {
-					rotation: self.rotation,
-					..Default::default()
-				}
-			)
-		}
+		draw_rotated(&assets.images[self.tex], full_footprint(self.rect), self.orientation, color);
 		draw_rectangle_lines(self.rect.x, self.rect.y, self.rect.w, self.rect.h, 3., BLUE);
+		draw_ports(&place_ports(self.rect, self.orientation, self.blueprint_type.size(), self.blueprint_type.ports()));
+
+		if let Some(reason) = &self.invalid_reason {
+			draw_text(reason, self.rect.x, self.rect.y - T_SIZE / 4., T_SIZE / 2., RED);
+		}
 	}
 }
 
@@ -165,84 +327,225 @@ pub struct DieselGenerator {
 	dead: bool,
 	rect: Rect,
 	collides: bool,
-	rotation: f32,
+	orientation: Orientation,
+	power: Option<PowerNode>,
+	slots: Vec<Slot>,
+	fuel: f32,
 
-	tex: *const Texture2D,
+	tex: &'static str,
+	interface_tex: Option<&'static str>,
 }
 
 impl DieselGenerator {
 	pub const SIZE: Vec2 = vec2(T_SIZE * 5., T_SIZE * 3.);
 	pub const NAME: &str = "Diesel Generator";
-
-	pub fn new(rect: Rect, assets: &AssetManager, rotation: f32) -> Self {
+	pub const MAX_HP: i32 = 30;
+	pub const BLUEPRINT: BlueprintType = BlueprintType::DieselGenerator;
+	pub const PORTS: &'static [PortDef] = &[
+		PortDef {tile: ivec2(0, 1), dir: ivec2(-1, 0), kind: PortKind::Input},
+	];
+	pub const OUTPUT: f32 = 100.;
+	pub const TANK_SIZE: f32 = 100.;
+	// Fuel burnt per turn at full load
+	pub const BURN_RATE: f32 = 1.;
+
+	const FUEL: usize = 0;
+
+	pub fn new(rect: Rect, orientation: Orientation) -> Self {
 		Self {
-			hp: 30,
+			hp: Self::MAX_HP,
 			dead: false,
 			// rect: Rect::new(pos.x + 1., pos.y + 1., (T_SIZE * 5.) - 2., (T_SIZE * 3.) - 2.),
 			rect,
 			collides: true,
-			rotation,
+			orientation,
+			// Nothing comes out until there's fuel in the tank
+			power: Some(PowerNode::producer(0.)),
+			slots: vec![
+				Slot::new("Fuel", SlotKind::Input, |item| item.fuel_value().is_some()),
+			],
+			fuel: 0.,
+
+			tex: "diesel_generator",
+			interface_tex: Some("diesel_generator_interface"),
+		}
+	}
 
-			tex: &assets.images["diesel_generator"] as *const Texture2D,
+	fn draw(&mut self, assets: &AssetManager) {
+		draw_rotated(&assets.images[self.tex], full_footprint(self.rect), self.orientation, WHITE);
+	}
+
+	fn tick(&mut self) {
+		// Top the tank up from the fuel slot whenever a whole item fits
+		if let Some(value) = self.slots[Self::FUEL].stack.and_then(|s| s.item.fuel_value()) {
+			if self.fuel + value <= Self::TANK_SIZE {
+				self.slots[Self::FUEL].take(1);
+				self.fuel += value;
+			}
+		}
+
+		if let Some(ref mut power) = self.power {
+			self.fuel = (self.fuel - Self::BURN_RATE * power.load).max(0.);
+
+			power.output = if self.fuel > 0. {
+				Self::OUTPUT
+			} else {
+				0.
+			};
 		}
 	}
 
-	fn draw(&mut self) {
-		unsafe {
-			draw_texture_ex(
-				&*self.tex,
-				self.rect.x, self.rect.y,
-				WHITE,
-				DrawTextureParams {
-					rotation: self.rotation,
-					..Default::default()
-				}
-			)
+	fn status(&self) -> String {
+		let load = self.power.as_ref().map(|p| p.load).unwrap_or(0.);
+		let fuel = format!("fuel {:.0} / {:.0}", self.fuel, Self::TANK_SIZE);
+
+		if self.fuel <= 0. {
+			"Out of fuel".to_string()
+		} else if load > 0. {
+			format!("Running at {:.0}% load, {}", load * 100., fuel)
+		} else {
+			format!("Idling, {}", fuel)
 		}
 	}
+
+	fn progress(&self) -> Option<f32> {
+		None
+	}
 }
 
 lazy_derive!(Structure, DieselGenerator);
 
+// Why a machine that has work to do can't get on with it, if it's down to power
+fn power_problem(power: Option<&PowerNode>) -> Option<&'static str> {
+	match power {
+		None => Some("Not powered"),
+		Some(power) if power.satisfaction <= 0. => Some("Not powered"),
+		Some(power) if power.speed() <= 0. => Some("Not enough power"),
+		Some(_) => None,
+	}
+}
+
 pub struct Crusher {
 	hp: i32,
 	dead: bool,
 	rect: Rect,
 	collides: bool,
-	rotation: f32,
-
-	tex: *const Texture2D,
+	orientation: Orientation,
+	power: Option<PowerNode>,
+	slots: Vec<Slot>,
+	progress: f32,
+	crushed_count: i32,
+
+	tex: &'static str,
+	interface_tex: Option<&'static str>,
 }
 
 impl Crusher {
 	pub const SIZE: Vec2 = vec2(T_SIZE * 5., T_SIZE * 3.);
 	pub const NAME: &str = "Crusher";
-
-	pub fn new(rect: Rect, assets: &AssetManager, rotation: f32) -> Self {
+	pub const MAX_HP: i32 = 30;
+	pub const BLUEPRINT: BlueprintType = BlueprintType::Crusher;
+	pub const PORTS: &'static [PortDef] = &[
+		PortDef {tile: ivec2(0, 1), dir: ivec2(-1, 0), kind: PortKind::Input},
+		PortDef {tile: ivec2(4, 1), dir: ivec2(1, 0), kind: PortKind::Output},
+	];
+	pub const DEMAND: f32 = 40.;
+	// Turns to crush one item at full power
+	pub const CRUSH_TURNS: f32 = 4.;
+	// Every this many crushed ores a piece of gravel comes out as well
+	pub const BYPRODUCT_EVERY: i32 = 3;
+
+	const INPUT: usize = 0;
+	const OUTPUT: usize = 1;
+	const BYPRODUCT: usize = 2;
+
+	pub fn new(rect: Rect, orientation: Orientation) -> Self {
 		Self {
-			hp: 30,
+			hp: Self::MAX_HP,
 			dead: false,
 			rect,
 			collides: true,
-			rotation,
-
-			tex: &assets.images["crusher"] as *const Texture2D,
+			orientation,
+			power: Some(PowerNode::consumer(Self::DEMAND)),
+			slots: vec![
+				Slot::new("Input", SlotKind::Input, |item| item.crushed().is_some()),
+				Slot::output("Output"),
+				Slot::output("Byproduct"),
+			],
+			progress: 0.,
+			crushed_count: 0,
+
+			tex: "crusher",
+			interface_tex: Some("crusher_interface"),
 		}
 	}
 
-	fn draw(&mut self) {
-		unsafe {
-			draw_texture_ex(
-				&*self.tex,
-				self.rect.x, self.rect.y,
-				WHITE,
-				DrawTextureParams {
-					rotation: self.rotation,
-					..Default::default()
+	fn draw(&mut self, assets: &AssetManager) {
+		draw_rotated(&assets.images[self.tex], full_footprint(self.rect), self.orientation, WHITE);
+	}
+
+	fn has_room_for(&self, product: ItemType, byproduct: Option<ItemType>) -> bool {
+		self.slots[Self::OUTPUT].space_for(product) > 0
+			&& byproduct.map(|b| self.slots[Self::BYPRODUCT].space_for(b) > 0).unwrap_or(true)
+	}
+
+	fn tick(&mut self) {
+		let speed = self.power.as_ref().map(|p| p.speed()).unwrap_or(0.);
+
+		let job = self.slots[Self::INPUT].stack
+			.and_then(|s| s.item.crushed())
+			.filter(|&(product, byproduct)| self.has_room_for(product, byproduct));
+
+		let Some((product, byproduct)) = job else {
+			self.progress = 0.;
+			if let Some(ref mut power) = self.power {
+				power.demand = 0.;
+			}
+			return;
+		};
+
+		if let Some(ref mut power) = self.power {
+			power.demand = Self::DEMAND;
+		}
+
+		self.progress += speed;
+
+		if self.progress >= Self::CRUSH_TURNS {
+			self.progress = 0.;
+			self.slots[Self::INPUT].take(1);
+			self.slots[Self::OUTPUT].insert(ItemStack::new(product, 1));
+
+			if let Some(byproduct) = byproduct {
+				self.crushed_count += 1;
+
+				if self.crushed_count % Self::BYPRODUCT_EVERY == 0 {
+					self.slots[Self::BYPRODUCT].insert(ItemStack::new(byproduct, 1));
 				}
-			)
+			}
 		}
 	}
+
+	fn status(&self) -> String {
+		let Some(input) = self.slots[Self::INPUT].stack else {
+			return "Waiting for ore".to_string();
+		};
+
+		let Some((product, byproduct)) = input.item.crushed() else {
+			return format!("Can't crush {}", input.item.name());
+		};
+
+		if !self.has_room_for(product, byproduct) {
+			"Output is full".to_string()
+		} else if let Some(problem) = power_problem(self.power.as_ref()) {
+			problem.to_string()
+		} else {
+			format!("Crushing {}", input.item.name())
+		}
+	}
+
+	fn progress(&self) -> Option<f32> {
+		Some(self.progress / Self::CRUSH_TURNS)
+	}
 }
 
 lazy_derive!(Structure, Crusher);
@@ -252,40 +555,110 @@ pub struct ArcFurnace {
 	dead: bool,
 	rect: Rect,
 	collides: bool,
-	rotation: f32,
-
-	tex: *const Texture2D,
+	orientation: Orientation,
+	power: Option<PowerNode>,
+	slots: Vec<Slot>,
+	progress: f32,
+	recipe: Option<&'static Recipe>,
+
+	tex: &'static str,
+	interface_tex: Option<&'static str>,
 }
 
 impl ArcFurnace {
 	pub const SIZE: Vec2 = vec2(T_SIZE * 2., T_SIZE * 2.);
 	pub const NAME: &str = "Arc Furnace";
+	pub const MAX_HP: i32 = 30;
+	pub const BLUEPRINT: BlueprintType = BlueprintType::ArcFurnace;
+	pub const PORTS: &'static [PortDef] = &[
+		PortDef {tile: ivec2(0, 1), dir: ivec2(-1, 0), kind: PortKind::Input},
+		PortDef {tile: ivec2(1, 1), dir: ivec2(1, 0), kind: PortKind::Output},
+	];
+	pub const DEMAND: f32 = 80.;
+
+	const OUTPUT: usize = 2;
 
-	pub fn new(rect: Rect, assets: &AssetManager, rotation: f32) -> Self {
+	pub fn new(rect: Rect, orientation: Orientation) -> Self {
 		Self {
-			hp: 30,
+			hp: Self::MAX_HP,
 			dead: false,
 			rect,
 			collides: true,
-			rotation,
+			orientation,
+			power: Some(PowerNode::consumer(Self::DEMAND)),
+			slots: vec![
+				Slot::new("Input", SlotKind::Input, |item| is_ingredient(SMELTING, item)),
+				Slot::new("Input", SlotKind::Input, |item| is_ingredient(SMELTING, item)),
+				Slot::output("Output"),
+			],
+			progress: 0.,
+			recipe: None,
+
+			tex: "arc_furnace",
+			interface_tex: Some("arc_furnace_interface"),
+		}
+	}
+
+	fn draw(&mut self, assets: &AssetManager) {
+		draw_rotated(&assets.images[self.tex], full_footprint(self.rect), self.orientation, WHITE);
+	}
 
-			tex: &assets.images["arc_furnace"] as *const Texture2D,
+	fn tick(&mut self) {
+		let speed = self.power.as_ref().map(|p| p.speed()).unwrap_or(0.);
+		let recipe = find_recipe(SMELTING, &self.slots, Self::OUTPUT);
+
+		let same_recipe = match (recipe, self.recipe) {
+			(Some(a), Some(b)) => std::ptr::eq(a, b),
+			(None, None) => true,
+			_ => false,
+		};
+
+		// Swapping what's in the slots starts the new recipe from scratch
+		if !same_recipe {
+			self.progress = 0.;
+			self.recipe = recipe;
+		}
+
+		let Some(recipe) = recipe else {
+			if let Some(ref mut power) = self.power {
+				power.demand = 0.;
+			}
+			return;
+		};
+
+		if let Some(ref mut power) = self.power {
+			power.demand = recipe.power;
+		}
+
+		self.progress += speed;
+
+		if self.progress >= recipe.turns {
+			self.progress = 0.;
+			recipe.consume_inputs(&mut self.slots);
+			self.slots[Self::OUTPUT].insert(recipe.output_stack());
 		}
 	}
 
-	fn draw(&mut self) {
-		unsafe {
-			draw_texture_ex(
-				&*self.tex,
-				self.rect.x, self.rect.y,
-				WHITE,
-				DrawTextureParams {
-					rotation: self.rotation,
-					..Default::default()
-				}
-			)
+	fn status(&self) -> String {
+		// Worked out from the slots as they are now, since they may have changed since the last tick
+		let Some(recipe) = find_recipe(SMELTING, &self.slots, Self::OUTPUT) else {
+			if SMELTING.iter().any(|r| r.has_inputs(&self.slots)) {
+				return "Output is full".to_string();
+			}
+
+			return "Waiting for a recipe".to_string();
+		};
+
+		if let Some(problem) = power_problem(self.power.as_ref()) {
+			problem.to_string()
+		} else {
+			format!("Smelting {}", recipe.output.0.name())
 		}
 	}
+
+	fn progress(&self) -> Option<f32> {
+		self.recipe.map(|r| self.progress / r.turns)
+	}
 }
 
 lazy_derive!(Structure, ArcFurnace);
@@ -295,40 +668,310 @@ pub struct SteelWall {
 	dead: bool,
 	rect: Rect,
 	collides: bool,
-	rotation: f32,
+	orientation: Orientation,
+	power: Option<PowerNode>,
+	slots: Vec<Slot>,
 
-	tex: *const Texture2D,
+	tex: &'static str,
+	interface_tex: Option<&'static str>,
 }
 
 impl SteelWall {
 	pub const SIZE: Vec2 = vec2(T_SIZE, T_SIZE);
 	pub const NAME: &str = "Steel Wall";
+	// Tougher than the rock around it, so it's worth walling a base in
+	pub const MAX_HP: i32 = 40;
+	pub const BLUEPRINT: BlueprintType = BlueprintType::SteelWall;
+	pub const PORTS: &'static [PortDef] = &[];
 
-	pub fn new(rect: Rect, assets: &AssetManager, rotation: f32) -> Self {
+	pub fn new(rect: Rect, orientation: Orientation) -> Self {
 		Self {
-			hp: 10,
+			hp: Self::MAX_HP,
 			dead: false,
 			rect,
 			collides: true,
-			rotation,
+			orientation,
+			power: None,
+			slots: Vec::new(),
 
-			tex: &assets.images["steel_plate_wall"] as *const Texture2D,
+			tex: "steel_plate_wall",
+			interface_tex: None,
 		}
 	}
 
-	fn draw(&mut self) {
-		unsafe {
-			draw_texture_ex(
-				&*self.tex,
-				self.rect.x, self.rect.y,
-				WHITE,
-				DrawTextureParams {
-					rotation: self.rotation,
-					..Default::default()
-				}
-			)
+	fn draw(&mut self, assets: &AssetManager) {
+		draw_rotated(&assets.images[self.tex], full_footprint(self.rect), self.orientation, WHITE);
+	}
+
+	fn tick(&mut self) {
+	}
+
+	fn status(&self) -> String {
+		"Holding".to_string()
+	}
+
+	fn progress(&self) -> Option<f32> {
+		None
+	}
+}
+
+lazy_derive!(Structure, SteelWall);
+
+pub struct Conveyor {
+	hp: i32,
+	dead: bool,
+	rect: Rect,
+	collides: bool,
+	orientation: Orientation,
+	power: Option<PowerNode>,
+	slots: Vec<Slot>,
+
+	tex: &'static str,
+	interface_tex: Option<&'static str>,
+}
+
+impl Conveyor {
+	pub const SIZE: Vec2 = vec2(T_SIZE, T_SIZE);
+	pub const NAME: &str = "Conveyor";
+	pub const MAX_HP: i32 = 5;
+	pub const BLUEPRINT: BlueprintType = BlueprintType::Conveyor;
+	// Takes from behind and passes forward, the sprite points north
+	pub const PORTS: &'static [PortDef] = &[
+		PortDef {tile: ivec2(0, 0), dir: ivec2(0, 1), kind: PortKind::Input},
+		PortDef {tile: ivec2(0, 0), dir: ivec2(0, -1), kind: PortKind::Output},
+	];
+
+	pub fn new(rect: Rect, orientation: Orientation) -> Self {
+		Self {
+			hp: Self::MAX_HP,
+			dead: false,
+			rect,
+			collides: false,
+			orientation,
+			power: None,
+			slots: vec![
+				Slot::new("Belt", SlotKind::Belt, |_| true),
+			],
+
+			tex: "conveyor",
+			interface_tex: None,
+		}
+	}
+
+	fn draw(&mut self, assets: &AssetManager) {
+		draw_rotated(&assets.images[self.tex], full_footprint(self.rect), self.orientation, WHITE);
+
+		if let Some(stack) = self.slots[0].stack {
+			let size = T_SIZE / 3.;
+			let center = self.rect.center();
+			draw_rectangle(center.x - size / 2., center.y - size / 2., size, size, stack.item.color());
+			draw_rectangle_lines(center.x - size / 2., center.y - size / 2., size, size, 2., BLACK);
+		}
+	}
+
+	fn tick(&mut self) {
+	}
+
+	fn status(&self) -> String {
+		if let Some(stack) = self.slots[0].stack {
+			format!("Carrying {} x{}", stack.item.name(), stack.amount)
+		} else {
+			"Empty".to_string()
 		}
 	}
+
+	fn progress(&self) -> Option<f32> {
+		None
+	}
 }
 
-lazy_derive!(Structure, SteelWall);
\ No newline at end of file
+lazy_derive!(Structure, Conveyor);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_utils::*;
+
+	fn rect(size: Vec2) -> Rect {
+		Rect::new(1., 1., size.x - 2., size.y - 2.)
+	}
+
+	#[test]
+	fn crusher_status_says_what_is_holding_it_up() {
+		let mut crusher = Crusher::new(rect(Crusher::SIZE), Orientation::North);
+		assert_eq!(crusher.status(), "Waiting for ore");
+
+		crusher.slots[Crusher::INPUT].insert(ItemStack::new(ItemType::Stone, 5));
+		assert_eq!(crusher.status(), "Not powered");
+
+		crusher.power.as_mut().unwrap().satisfaction = 1.;
+		assert_eq!(crusher.status(), format!("Crushing {}", ItemType::Stone.name()));
+
+		crusher.slots[Crusher::OUTPUT].insert(ItemStack::new(ItemType::Gravel, ItemType::Gravel.max_stack()));
+		assert_eq!(crusher.status(), "Output is full");
+
+		crusher.slots[Crusher::INPUT].stack = Some(ItemStack::new(ItemType::IronIngot, 1));
+		assert_eq!(crusher.status(), format!("Can't crush {}", ItemType::IronIngot.name()));
+	}
+
+	#[test]
+	fn crushers_turn_ore_out_at_their_own_pace() {
+		let mut crusher = Crusher::new(rect(Crusher::SIZE), Orientation::North);
+		crusher.power.as_mut().unwrap().satisfaction = 1.;
+		crusher.slots[Crusher::INPUT].insert(ItemStack::new(ItemType::IronOre, 6));
+
+		for crushed in 1..=6 {
+			for _ in 1..Crusher::CRUSH_TURNS as i32 {
+				crusher.tick();
+			}
+			assert_eq!(crusher.slots[Crusher::INPUT].stack.unwrap().amount, 7 - crushed);
+
+			crusher.tick();
+			assert_eq!(crusher.slots[Crusher::OUTPUT].stack, Some(ItemStack::new(ItemType::CrushedIronOre, crushed)));
+
+			// Gravel only comes out with every few ores
+			let gravel = crusher.slots[Crusher::BYPRODUCT].stack.map(|s| s.amount).unwrap_or(0);
+			assert_eq!(gravel, crushed / Crusher::BYPRODUCT_EVERY);
+		}
+
+		assert_eq!(crusher.slots[Crusher::INPUT].stack, None);
+		crusher.tick();
+		assert_eq!(crusher.power.as_ref().unwrap().demand, 0.);
+	}
+
+	#[test]
+	fn crushers_slow_down_on_half_power() {
+		let mut crusher = Crusher::new(rect(Crusher::SIZE), Orientation::North);
+		crusher.power.as_mut().unwrap().satisfaction = 0.5;
+		crusher.slots[Crusher::INPUT].insert(ItemStack::new(ItemType::Stone, 1));
+
+		for _ in 1..(Crusher::CRUSH_TURNS * 2.) as i32 {
+			crusher.tick();
+		}
+		assert_eq!(crusher.slots[Crusher::OUTPUT].stack, None);
+
+		crusher.tick();
+		assert_eq!(crusher.slots[Crusher::OUTPUT].stack, Some(ItemStack::new(ItemType::Gravel, 1)));
+		assert_eq!(crusher.slots[Crusher::BYPRODUCT].stack, None);
+	}
+
+	#[test]
+	fn furnace_status_follows_the_slots() {
+		let mut furnace = ArcFurnace::new(rect(ArcFurnace::SIZE), Orientation::North);
+		furnace.power.as_mut().unwrap().satisfaction = 1.;
+		assert_eq!(furnace.status(), "Waiting for a recipe");
+
+		furnace.slots[0].insert(ItemStack::new(ItemType::CrushedIronOre, 2));
+		assert_eq!(furnace.status(), format!("Smelting {}", ItemType::IronIngot.name()));
+
+		furnace.slots[ArcFurnace::OUTPUT].insert(ItemStack::new(ItemType::IronIngot, ItemType::IronIngot.max_stack()));
+		assert_eq!(furnace.status(), "Output is full");
+	}
+
+	#[test]
+	fn furnaces_smelt_their_inputs() {
+		let mut furnace = ArcFurnace::new(rect(ArcFurnace::SIZE), Orientation::North);
+		furnace.power.as_mut().unwrap().satisfaction = 1.;
+		furnace.slots[0].insert(ItemStack::new(ItemType::CrushedIronOre, 3));
+
+		let recipe = find_recipe(SMELTING, &furnace.slots, ArcFurnace::OUTPUT).unwrap();
+		assert_eq!(recipe.output, (ItemType::IronIngot, 1));
+
+		for _ in 1..recipe.turns as i32 {
+			furnace.tick();
+		}
+		assert_eq!(furnace.power.as_ref().unwrap().demand, recipe.power);
+		assert_eq!(furnace.slots[ArcFurnace::OUTPUT].stack, None);
+
+		furnace.tick();
+		assert_eq!(furnace.slots[ArcFurnace::OUTPUT].stack, Some(recipe.output_stack()));
+		assert_eq!(furnace.slots[0].stack, Some(ItemStack::new(ItemType::CrushedIronOre, 1)));
+
+		// Not enough left for another go
+		furnace.tick();
+		assert_eq!(furnace.progress(), None);
+		assert_eq!(furnace.power.as_ref().unwrap().demand, 0.);
+	}
+
+	#[test]
+	fn swapping_inputs_starts_over() {
+		let mut furnace = ArcFurnace::new(rect(ArcFurnace::SIZE), Orientation::North);
+		furnace.power.as_mut().unwrap().satisfaction = 1.;
+		furnace.slots[0].insert(ItemStack::new(ItemType::CrushedIronOre, 2));
+
+		furnace.tick();
+		furnace.tick();
+		assert!(furnace.progress().unwrap() > 0.);
+
+		furnace.slots[0].stack = Some(ItemStack::new(ItemType::CrushedGoldOre, 2));
+		furnace.tick();
+		assert_eq!(furnace.recipe.unwrap().output.0, ItemType::GoldIngot);
+		assert_eq!(furnace.progress, 1.);
+	}
+
+	#[test]
+	fn generators_cut_out_when_the_fuel_runs_dry() {
+		let mut generator = DieselGenerator::new(rect(DieselGenerator::SIZE), Orientation::North);
+		generator.slots[DieselGenerator::FUEL].insert(ItemStack::new(ItemType::LatalaShroom, 1));
+		let fuel = ItemType::LatalaShroom.fuel_value().unwrap();
+
+		// Nothing burns while nobody draws on it
+		generator.tick();
+		assert_eq!(generator.slots[DieselGenerator::FUEL].stack, None);
+		assert_eq!(generator.fuel, fuel);
+		assert_eq!(generator.power.as_ref().unwrap().output, DieselGenerator::OUTPUT);
+
+		generator.power.as_mut().unwrap().load = 1.;
+
+		for turn in 1..(fuel / DieselGenerator::BURN_RATE) as i32 {
+			generator.tick();
+			assert_eq!(generator.fuel, fuel - turn as f32 * DieselGenerator::BURN_RATE);
+			assert_eq!(generator.power.as_ref().unwrap().output, DieselGenerator::OUTPUT);
+		}
+
+		generator.tick();
+		assert_eq!(generator.fuel, 0.);
+		assert_eq!(generator.power.as_ref().unwrap().output, 0.);
+		assert_eq!(generator.status(), "Out of fuel");
+
+		generator.tick();
+		assert_eq!(generator.fuel, 0.);
+	}
+
+	#[test]
+	fn rotating_turns_the_footprint_and_ports_with_it() {
+		// Footprint size, then the input and output ports as a tile from the top left and the way they open
+		let expected = [
+			(Orientation::North, ivec2(5, 3), (ivec2(0, 1), ivec2(-1, 0)), (ivec2(4, 1), ivec2(1, 0))),
+			(Orientation::East, ivec2(3, 5), (ivec2(1, 0), ivec2(0, -1)), (ivec2(1, 4), ivec2(0, 1))),
+			(Orientation::South, ivec2(5, 3), (ivec2(4, 1), ivec2(1, 0)), (ivec2(0, 1), ivec2(-1, 0))),
+			(Orientation::West, ivec2(3, 5), (ivec2(1, 4), ivec2(0, 1)), (ivec2(1, 0), ivec2(0, -1))),
+		];
+
+		let mut blueprint = Blueprint::new(tile(0, 0), BlueprintType::Crusher);
+
+		for (orientation, size, input, output) in expected {
+			let mut level = Level::new(1234);
+			let mut inventory = Inventory::new(100.);
+
+			assert_eq!(blueprint.orientation, orientation);
+			blueprint.place(&mut level, &mut inventory);
+
+			let structure = level.structure_at(blueprint.rect.center()).unwrap();
+			let origin = full_footprint(structure.rect()).point();
+			assert_eq!((full_footprint(structure.rect()).size() / T_SIZE).round().as_ivec2(), size);
+
+			let ports = structure.ports();
+			assert_eq!(ports.iter().map(|p| p.kind).collect::<Vec<PortKind>>(), vec![PortKind::Input, PortKind::Output]);
+
+			for (port, (tile, dir)) in ports.iter().zip([input, output]) {
+				assert_eq!(((port.tile - origin) / T_SIZE).round().as_ivec2(), tile, "{:?}", orientation);
+				assert_eq!(((port.outside - port.tile) / T_SIZE).round().as_ivec2(), dir, "{:?}", orientation);
+			}
+
+			blueprint.rotate_right(&level, &inventory);
+		}
+
+		assert_eq!(blueprint.orientation, Orientation::North);
+	}
+}
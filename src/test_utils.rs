@@ -0,0 +1,43 @@
+use macroquad::prelude::*;
+
+use crate::primitives::*;
+use crate::generation::*;
+use crate::level::*;
+use crate::structures::*;
+
+pub fn tile(x: i32, y: i32) -> Vec2 {
+	vec2(x as f32, y as f32) * T_SIZE
+}
+
+// Mines out a rectangle of tiles and sweeps up whatever falls out
+pub fn clear(level: &mut Level, x: i32, y: i32, w: i32, h: i32) {
+	for tx in x..x + w {
+		for ty in y..y + h {
+			level.damage_terrain(tile(tx, ty), f32::MAX);
+			level.take_items_at(tile(tx, ty));
+		}
+	}
+}
+
+pub fn put_wall(level: &mut Level, x: i32, y: i32) {
+	let pos = tile(x, y);
+	let chunk = level.get_chunk_mut(pos).unwrap();
+	let (lx, ly) = chunk.local_tile(pos);
+
+	chunk.terrain[ly][lx] = true;
+	chunk.colliders.push(Wall::new(pos, WallData::CaveWall {
+		hardness: 3.,
+	}));
+}
+
+// The rect of a structure of `size` with its top left on the tile, inset like a placed blueprint
+pub fn footprint(x: i32, y: i32, size: Vec2) -> Rect {
+	let pos = tile(x, y);
+	Rect::new(pos.x + 1., pos.y + 1., size.x - 2., size.y - 2.)
+}
+
+pub fn place_steel_wall(level: &mut Level, x: i32, y: i32) -> Rect {
+	let rect = footprint(x, y, SteelWall::SIZE);
+	level.add_structure(Box::new(SteelWall::new(rect, Orientation::North)));
+	rect
+}
@@ -1,5 +1,11 @@
 use macroquad::prelude::*;
 
+use hot_assets::*;
+
+use crate::power::*;
+use crate::items::*;
+use crate::structures::*;
+
 pub use crate::lazy_derive;
 
 #[macro_export]
@@ -10,6 +16,14 @@ macro_rules! lazy_derive {
 				self.hp
 			}
 
+			fn max_hp(&self) -> i32 {
+				$name::MAX_HP
+			}
+
+			fn dead(&self) -> bool {
+				self.dead
+			}
+
 			fn hurt(&mut self, damage: i32) -> bool {
 				self.hp -= damage;
 
@@ -28,6 +42,22 @@ macro_rules! lazy_derive {
 				self.hp
 			}
 
+			fn max_hp(&self) -> i32 {
+				$name::MAX_HP
+			}
+
+			fn dead(&self) -> bool {
+				self.dead
+			}
+
+			fn name(&self) -> &'static str {
+				$name::NAME
+			}
+
+			fn blueprint_type(&self) -> BlueprintType {
+				$name::BLUEPRINT
+			}
+
 			fn hurt(&mut self, damage: i32) -> bool {
 				self.hp -= damage;
 
@@ -47,27 +77,57 @@ macro_rules! lazy_derive {
 				self.collides
 			}
 
-			fn draw(&mut self) {
-				self.draw();
+			fn power(&self) -> Option<&PowerNode> {
+				self.power.as_ref()
 			}
 
-			fn draw_blueprint(&mut self, valid: bool) {
-				unsafe {
-					let color = if valid {
-						GREEN
-					} else {
-						RED
-					};
+			fn power_mut(&mut self) -> Option<&mut PowerNode> {
+				self.power.as_mut()
+			}
 
-					draw_texture_ex(
-						&*self.tex,
-						self.rect.x, self.rect.y,
-						color,
-						DrawTextureParams {
-							..Default::default()
-						}
-					)
-				}
+			fn ports(&self) -> Vec<Port> {
+				place_ports(self.rect, self.orientation, $name::SIZE, $name::PORTS)
+			}
+
+			fn slots(&self) -> &Vec<Slot> {
+				&self.slots
+			}
+
+			fn slots_mut(&mut self) -> &mut Vec<Slot> {
+				&mut self.slots
+			}
+
+			fn tick(&mut self) {
+				self.tick();
+			}
+
+			fn status(&self) -> String {
+				self.status()
+			}
+
+			fn progress(&self) -> Option<f32> {
+				self.progress()
+			}
+
+			fn interface_tex(&self) -> Option<&'static str> {
+				self.interface_tex
+			}
+
+			fn draw(&mut self, assets: &AssetManager) {
+				self.draw(assets);
+
+				draw_ports(&self.ports());
+				draw_damage(self.rect, self.hp, $name::MAX_HP, assets);
+			}
+
+			fn draw_blueprint(&mut self, valid: bool, assets: &AssetManager) {
+				let color = if valid {
+					GREEN
+				} else {
+					RED
+				};
+
+				draw_rotated(&assets.images[self.tex], full_footprint(self.rect), self.orientation, color);
 			}
 
 			fn move_toward(&mut self, rhs: Vec2) {
@@ -79,15 +139,31 @@ macro_rules! lazy_derive {
 
 pub trait Damageable {
 	fn hp(&self) -> i32;
+	fn max_hp(&self) -> i32;
+	fn dead(&self) -> bool;
 	fn hurt(&mut self, _damage: i32) -> bool;
 }
 
 pub trait Structure {
 	fn hp(&self) -> i32;
+	fn max_hp(&self) -> i32;
+	fn dead(&self) -> bool;
+	fn name(&self) -> &'static str;
+	fn blueprint_type(&self) -> BlueprintType;
 	fn rect(&self) -> Rect;
 	fn collides(&self) -> bool;
+	fn power(&self) -> Option<&PowerNode>;
+	fn power_mut(&mut self) -> Option<&mut PowerNode>;
+	fn ports(&self) -> Vec<Port>;
+	fn slots(&self) -> &Vec<Slot>;
+	fn slots_mut(&mut self) -> &mut Vec<Slot>;
+	fn tick(&mut self);
+	fn status(&self) -> String;
+	// How far along the current job is, 0 to 1
+	fn progress(&self) -> Option<f32>;
+	fn interface_tex(&self) -> Option<&'static str>;
 	fn hurt(&mut self, _damage: i32) -> bool;
-	fn draw(&mut self);
-	fn draw_blueprint(&mut self, valid: bool);
+	fn draw(&mut self, assets: &AssetManager);
+	fn draw_blueprint(&mut self, valid: bool, assets: &AssetManager);
 	fn move_toward(&mut self, rhs: Vec2);
 }
\ No newline at end of file